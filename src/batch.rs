@@ -0,0 +1,357 @@
+//! `qq batch`: answering a file of questions a few at a time, with results
+//! saved as they arrive so an interrupted run can pick up where it stopped.
+
+use anyhow::Result;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+use crate::budget::{self, InFlight, Ledger};
+use crate::config::Config;
+use crate::prompt::{self, Prompt};
+use crate::providers::{self, Answer};
+
+/// One question. Lines starting with `{` are JSON objects that can also
+/// set an id, model, max_tokens or persona for just that question.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchItem {
+    pub id: Option<String>,
+    pub question: String,
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    #[serde(rename = "as")]
+    pub persona: Option<String>,
+}
+
+/// The outcome for one question, as written to the JSONL output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub question: String,
+    pub model: String,
+    #[serde(default)]
+    pub max_tokens: u32,
+    #[serde(default, rename = "as", skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+    pub answer: Option<String>,
+    pub error: Option<String>,
+    #[serde(default)]
+    pub truncated: bool,
+    #[serde(default)]
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BatchFormat {
+    Jsonl,
+    Markdown,
+}
+
+impl BatchFormat {
+    /// Markdown for `.md` outputs, JSONL for anything else.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("md" | "markdown") => Self::Markdown,
+            _ => Self::Jsonl,
+        }
+    }
+}
+
+pub struct BatchOptions {
+    pub output: PathBuf,
+    pub format: BatchFormat,
+    pub jobs: usize,
+}
+
+impl BatchOptions {
+    /// Where results are saved while the batch runs: the output itself for
+    /// JSONL, a file next to it for a Markdown report.
+    pub fn progress_path(&self) -> PathBuf {
+        match self.format {
+            BatchFormat::Jsonl => self.output.clone(),
+            BatchFormat::Markdown => self.output.with_extension("progress.jsonl"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    pub answered: usize,
+    /// Answered by an earlier run
+    pub resumed: usize,
+    pub failed: usize,
+    /// Why the batch stopped before the end, if it did
+    pub stopped: Option<String>,
+}
+
+/// Reads the questions: one per line, blank lines and `#` comments skipped.
+pub fn parse_items(content: &str) -> Result<Vec<BatchItem>> {
+    let mut items = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let item = if line.starts_with('{') {
+            serde_json::from_str(line).map_err(|e| anyhow::anyhow!("Line {}: {}", number + 1, e))?
+        } else {
+            BatchItem {
+                question: line.to_string(),
+                ..BatchItem::default()
+            }
+        };
+        if item.question.trim().is_empty() {
+            return Err(anyhow::anyhow!("Line {}: no question", number + 1));
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// Answers saved by an earlier run that still match the questions and the
+/// model, max_tokens and persona they'd be asked with now. Failed ones are
+/// left out so they're asked again, and so are answers from a fallback.
+pub fn load_finished(path: &Path, items: &[BatchItem], config: &Config) -> Vec<BatchResult> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+
+    let mut finished: HashMap<usize, BatchResult> = HashMap::new();
+    // A run killed mid-write can leave half a line, so skip what won't parse
+    for result in content.lines().filter_map(|line| serde_json::from_str::<BatchResult>(line).ok()) {
+        let matches = items.get(result.index).is_some_and(|item| {
+            let mut item_config = config.clone();
+            item.question == result.question
+                && item.persona == result.persona
+                && item_prompt(item, &mut item_config).is_ok()
+                && item_config.model == result.model
+                && item_config.max_tokens == result.max_tokens
+        });
+        if matches && result.error.is_none() {
+            finished.insert(result.index, result);
+        }
+    }
+    let mut finished: Vec<BatchResult> = finished.into_values().collect();
+    finished.sort_by_key(|result| result.index);
+    finished
+}
+
+/// The results as a Markdown document, in question order.
+pub fn markdown_report(results: &[BatchResult]) -> String {
+    let mut report = String::new();
+    for result in results {
+        let heading = result.id.as_deref().unwrap_or(&result.question);
+        report.push_str(&format!("## {}\n\n", heading.trim()));
+        if result.id.is_some() {
+            report.push_str(&format!("> {}\n\n", result.question.trim()));
+        }
+        match (&result.answer, &result.error) {
+            (Some(answer), _) => report.push_str(answer.trim()),
+            (None, Some(error)) => report.push_str(&format!("*No answer: {}*", error)),
+            (None, None) => {}
+        }
+        if result.truncated {
+            report.push_str("\n\n*(answer cut off at max_tokens)*");
+        }
+        report.push_str("\n\n");
+    }
+    report
+}
+
+/// Answers every question not already answered in the progress file,
+/// `options.jobs` at a time, then writes the output in question order.
+pub async fn run(config: &Config, items: &[BatchItem], options: &BatchOptions) -> Result<BatchSummary> {
+    let progress_path = options.progress_path();
+    let mut results = load_finished(&progress_path, items, config);
+    let mut summary = BatchSummary {
+        resumed: results.len(),
+        ..BatchSummary::default()
+    };
+
+    // Start the progress file over with just the answers being kept
+    if let Some(dir) = progress_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let mut progress = fs::File::create(&progress_path)?;
+    for result in &results {
+        writeln!(progress, "{}", serde_json::to_string(result)?)?;
+    }
+
+    let done: HashSet<usize> = results.iter().map(|result| result.index).collect();
+    let mut pending = (0..items.len()).filter(|index| !done.contains(index));
+
+    let bars = MultiProgress::new();
+    let overall = bars.add(ProgressBar::new(items.len() as u64));
+    overall.set_style(
+        ProgressStyle::default_bar()
+            .template("{bar:30.cyan/blue} {pos}/{len} answered {msg}")
+            .unwrap(),
+    );
+    overall.set_position(results.len() as u64);
+
+    let mut ledger = Ledger::load()?;
+    let mut running: JoinSet<(usize, String, u32, Result<Answer>)> = JoinSet::new();
+    let mut workers: HashMap<usize, ProgressBar> = HashMap::new();
+    // What each running request could cost at most
+    let mut estimates: HashMap<usize, f64> = HashMap::new();
+
+    loop {
+        while running.len() < options.jobs.max(1) && summary.stopped.is_none() {
+            let Some(index) = pending.next() else { break };
+            let item = &items[index];
+            let mut item_config = config.clone();
+            let prompt = match item_prompt(item, &mut item_config) {
+                Ok(prompt) => prompt,
+                Err(e) => {
+                    let result = failed(index, item, &item_config, e.to_string());
+                    writeln!(progress, "{}", serde_json::to_string(&result)?)?;
+                    results.push(result);
+                    summary.failed += 1;
+                    overall.inc(1);
+                    continue;
+                }
+            };
+
+            let estimate = budget::estimate_cost(
+                &item_config.model,
+                prompt.system_text().len() + prompt.question.len(),
+                item_config.max_tokens,
+            );
+            // Running requests aren't in the ledger yet, but will be soon
+            let in_flight = InFlight {
+                requests: estimates.len(),
+                cost: estimates.values().sum(),
+            };
+            if let Some(reason) = budget::check_in_flight(&config.budget, &ledger, in_flight, estimate, budget::now()) {
+                summary.stopped = Some(reason);
+                break;
+            }
+
+            let provider = providers::from_config(&item_config, None)?;
+            let worker = bars.add(ProgressBar::new_spinner());
+            worker.set_message(item.question.lines().next().unwrap_or_default().to_string());
+            worker.enable_steady_tick(Duration::from_millis(120));
+            workers.insert(index, worker);
+            estimates.insert(index, estimate);
+
+            let model = item_config.model.clone();
+            let max_tokens = item_config.max_tokens;
+            // No cancel::abortable here: Ctrl-C just exits, and every answer
+            // so far is already in the progress file for the next run
+            running.spawn(async move {
+                let answer = provider.ask(&prompt).await;
+                let model = provider.fell_back_to().map_or(model, |target| target.model);
+                (index, model, max_tokens, answer)
+            });
+        }
+
+        let Some(finished) = running.join_next().await else { break };
+        let (index, model, max_tokens, answer) = finished?;
+        if let Some(worker) = workers.remove(&index) {
+            worker.finish_and_clear();
+        }
+        estimates.remove(&index);
+
+        let item = &items[index];
+        let result = match answer {
+            Ok(answer) => {
+                if let Err(e) = ledger.record(&model, &answer.usage) {
+                    bars.println(format!("⚠️  Could not record usage: {}", e)).ok();
+                }
+                summary.answered += 1;
+                BatchResult {
+                    index,
+                    id: item.id.clone(),
+                    question: item.question.clone(),
+                    persona: item.persona.clone(),
+                    max_tokens,
+                    cost_usd: budget::cost_of(&model, &answer.usage),
+                    truncated: answer.is_truncated(),
+                    answer: Some(answer.text),
+                    error: None,
+                    model,
+                }
+            }
+            Err(e) => {
+                summary.failed += 1;
+                BatchResult {
+                    model,
+                    max_tokens,
+                    ..failed(index, item, config, e.to_string())
+                }
+            }
+        };
+        writeln!(progress, "{}", serde_json::to_string(&result)?)?;
+        results.push(result);
+        overall.inc(1);
+    }
+    overall.finish_and_clear();
+
+    results.sort_by_key(|result| result.index);
+    match options.format {
+        BatchFormat::Jsonl => {
+            let lines: Vec<String> = results.iter().map(serde_json::to_string).collect::<Result<_, _>>()?;
+            write_atomically(&options.output, &(lines.join("\n") + "\n"))?;
+        }
+        BatchFormat::Markdown => {
+            write_atomically(&options.output, &markdown_report(&results))?;
+            // Keep the saved answers around until there's nothing left to retry
+            if summary.failed == 0 && summary.stopped.is_none() {
+                let _ = fs::remove_file(&progress_path);
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// The prompt for one question, with its persona and then its own
+/// settings applied to `config`.
+fn item_prompt(item: &BatchItem, config: &mut Config) -> Result<Prompt> {
+    let mut prompt = Prompt::new(&item.question)?;
+    if let Some(name) = &item.persona {
+        let persona = prompt::find_persona(config, name).ok_or_else(|| anyhow::anyhow!("No persona named '{}'", name))?;
+        prompt.system = prompt::persona_system_prompt(&persona);
+        persona.apply(config);
+    }
+    if let Some(model) = &item.model {
+        config.model = model.clone();
+    }
+    if let Some(max_tokens) = item.max_tokens {
+        config.max_tokens = max_tokens;
+    }
+    Ok(prompt)
+}
+
+fn failed(index: usize, item: &BatchItem, config: &Config, error: String) -> BatchResult {
+    BatchResult {
+        index,
+        id: item.id.clone(),
+        question: item.question.clone(),
+        model: config.model.clone(),
+        max_tokens: config.max_tokens,
+        persona: item.persona.clone(),
+        answer: None,
+        error: Some(error),
+        truncated: false,
+        cost_usd: 0.0,
+    }
+}
+
+/// Writes to a fresh file next to `path` and renames it over `path`, so a
+/// crash never leaves a half-written output behind.
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    temp.write_all(content.as_bytes())?;
+    temp.persist(path)?;
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+
+use crate::config::{BudgetConfig, Config};
+use crate::providers::Usage;
+
+/// USD price per million tokens for a model family.
+#[derive(Debug, Clone, Copy)]
+pub struct Pricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+pub fn pricing_for(model: &str) -> Pricing {
+    let (input_per_mtok, output_per_mtok) = if model.contains("opus-4-5") {
+        (5.0, 25.0)
+    } else if model.contains("opus") {
+        (15.0, 75.0)
+    } else if model.contains("haiku-4") {
+        (1.0, 5.0)
+    } else if model.contains("3-5-haiku") {
+        (0.8, 4.0)
+    } else if model.contains("haiku") {
+        (0.25, 1.25)
+    } else {
+        // Sonnet pricing, also used for anything we don't recognise
+        (3.0, 15.0)
+    };
+    Pricing { input_per_mtok, output_per_mtok }
+}
+
+pub fn cost_of(model: &str, usage: &Usage) -> f64 {
+    let pricing = pricing_for(model);
+    // Cache writes cost 25% more than regular input, cache reads 90% less
+    (usage.input_tokens as f64 * pricing.input_per_mtok
+        + usage.cache_creation_input_tokens as f64 * pricing.input_per_mtok * 1.25
+        + usage.cache_read_input_tokens as f64 * pricing.input_per_mtok * 0.1
+        + usage.output_tokens as f64 * pricing.output_per_mtok)
+        / 1_000_000.0
+}
+
+/// Worst-case cost of a request before it is sent. Input tokens are guessed
+/// from the prompt size (~4 characters per token) and the answer is assumed
+/// to use the whole `max_tokens` allowance.
+pub fn estimate_cost(model: &str, prompt_chars: usize, max_tokens: u32) -> f64 {
+    let usage = Usage {
+        input_tokens: prompt_chars.div_ceil(4) as u32,
+        output_tokens: max_tokens,
+        ..Usage::default()
+    };
+    cost_of(model, &usage)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageRecord {
+    pub timestamp: u64,
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: u32,
+    #[serde(default)]
+    pub cache_read_input_tokens: u32,
+    pub cost_usd: f64,
+}
+
+/// Append-only log of every answered request, stored as JSON lines.
+pub struct Ledger {
+    records: Vec<UsageRecord>,
+}
+
+impl Ledger {
+    /// A ledger holding `records`, without reading or writing the file.
+    pub fn from_records(records: Vec<UsageRecord>) -> Self {
+        Self { records }
+    }
+
+    pub fn path() -> Result<PathBuf> {
+        let mut path = Config::config_dir()?;
+        path.push("usage.jsonl");
+        Ok(path)
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        let records = match fs::read_to_string(path) {
+            // Skip lines we can't parse rather than locking the user out
+            Ok(content) => content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        Ok(Self { records })
+    }
+
+    pub fn record(&mut self, model: &str, usage: &Usage) -> Result<()> {
+        let record = UsageRecord {
+            timestamp: now(),
+            model: model.to_string(),
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cache_creation_input_tokens: usage.cache_creation_input_tokens,
+            cache_read_input_tokens: usage.cache_read_input_tokens,
+            cost_usd: cost_of(model, usage),
+        };
+
+        fs::create_dir_all(Config::config_dir()?)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::path()?)?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+
+        self.records.push(record);
+        Ok(())
+    }
+
+    pub fn spent_today(&self, now: u64) -> f64 {
+        let today = utc_date(now);
+        self.records
+            .iter()
+            .filter(|r| utc_date(r.timestamp) == today)
+            .map(|r| r.cost_usd)
+            .sum()
+    }
+
+    pub fn spent_this_month(&self, now: u64) -> f64 {
+        let (year, month, _) = utc_date(now);
+        self.records
+            .iter()
+            .filter(|r| {
+                let (y, m, _) = utc_date(r.timestamp);
+                y == year && m == month
+            })
+            .map(|r| r.cost_usd)
+            .sum()
+    }
+
+    pub fn requests_in_last_hour(&self, now: u64) -> usize {
+        self.records
+            .iter()
+            .filter(|r| r.timestamp + 3600 > now)
+            .count()
+    }
+}
+
+/// Requests already sent but not yet answered, so not in the ledger.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InFlight {
+    pub requests: usize,
+    /// What they could cost at most, from their estimates
+    pub cost: f64,
+}
+
+/// Returns a human readable reason if sending a request estimated to cost
+/// `estimate` would break one of the configured limits.
+pub fn check(budget: &BudgetConfig, ledger: &Ledger, estimate: f64, now: u64) -> Option<String> {
+    check_in_flight(budget, ledger, InFlight::default(), estimate, now)
+}
+
+/// Like [`check`], counting `in_flight` requests as if they'd already
+/// cost their whole estimate.
+pub fn check_in_flight(budget: &BudgetConfig, ledger: &Ledger, in_flight: InFlight, estimate: f64, now: u64) -> Option<String> {
+    let running = if in_flight.requests > 0 { " (counting requests still running)" } else { "" };
+
+    if let Some(limit) = budget.max_requests_per_hour {
+        let count = ledger.requests_in_last_hour(now) + in_flight.requests;
+        if count >= limit as usize {
+            return Some(format!(
+                "You've made {} requests in the last hour{} (limit: {}).",
+                count, running, limit
+            ));
+        }
+    }
+
+    if let Some(limit) = budget.daily_usd {
+        let spent = ledger.spent_today(now) + in_flight.cost;
+        if spent + estimate > limit {
+            return Some(format!(
+                "This request (up to ${:.4}) would exceed your daily budget: ${:.2} of ${:.2} spent today{}.",
+                estimate, spent, limit, running
+            ));
+        }
+    }
+
+    if let Some(limit) = budget.monthly_usd {
+        let spent = ledger.spent_this_month(now) + in_flight.cost;
+        if spent + estimate > limit {
+            return Some(format!(
+                "This request (up to ${:.4}) would exceed your monthly budget: ${:.2} of ${:.2} spent this month{}.",
+                estimate, spent, limit, running
+            ));
+        }
+    }
+
+    None
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Converts a unix timestamp to a UTC (year, month, day).
+pub fn utc_date(timestamp: u64) -> (i64, u32, u32) {
+    // Howard Hinnant's civil_from_days algorithm
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+use anyhow::Result;
+
+use crate::budget;
+use crate::config::{CacheConfig, Config};
+use crate::providers::{Answer, Provider};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CacheEntry {
+    pub created_at: u64,
+    pub last_used: u64,
+    pub question: String,
+    pub text: String,
+    #[serde(default)]
+    pub thinking: Option<String>,
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+}
+
+/// On-disk answer cache, one JSON file per entry named after its key.
+pub struct Cache {
+    dir: PathBuf,
+    ttl_secs: u64,
+    max_entries: usize,
+}
+
+impl Cache {
+    pub fn open(config: &CacheConfig) -> Result<Self> {
+        Self::open_in(Config::cache_dir()?.join("responses"), config)
+    }
+
+    /// A cache kept in `dir` instead of the usual place.
+    pub fn open_in(dir: PathBuf, config: &CacheConfig) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            ttl_secs: config.ttl_hours * 3600,
+            max_entries: config.max_entries,
+        })
+    }
+
+    /// Hashes everything that can change the answer into a stable key.
+    /// `params` covers request settings such as max_tokens and thinking.
+    pub fn key(provider: &str, model: &str, params: &str, system_prompt: &str, question: &str) -> String {
+        let mut hasher = Sha256::new();
+        for part in [provider, model, params, system_prompt, question] {
+            hasher.update(part.as_bytes());
+            hasher.update([0u8]);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CacheEntry> {
+        let path = self.entry_path(key);
+        let content = fs::read_to_string(&path).ok()?;
+        let mut entry: CacheEntry = serde_json::from_str(&content).ok()?;
+
+        let now = budget::now();
+        if now.saturating_sub(entry.created_at) > self.ttl_secs {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+
+        // Touch the entry so eviction keeps recently used answers around
+        entry.last_used = now;
+        if let Ok(content) = serde_json::to_string(&entry) {
+            let _ = fs::write(&path, content);
+        }
+
+        Some(entry)
+    }
+
+    pub fn put(&self, key: &str, question: &str, answer: &Answer) -> Result<()> {
+        let now = budget::now();
+        let entry = CacheEntry {
+            created_at: now,
+            last_used: now,
+            question: question.to_string(),
+            text: answer.text.clone(),
+            thinking: answer.thinking.clone(),
+            stop_reason: answer.stop_reason.clone(),
+        };
+        fs::write(self.entry_path(key), serde_json::to_string(&entry)?)?;
+
+        self.evict()
+    }
+
+    /// Saves a fresh answer from `provider`, unless one of its fallbacks
+    /// gave it: a stand-in's answer isn't what the configured model would
+    /// have said.
+    pub fn put_answer(&self, key: &str, question: &str, provider: &dyn Provider, answer: &Answer) -> Result<()> {
+        if provider.fell_back_to().is_some() {
+            return Ok(());
+        }
+        self.put(key, question, answer)
+    }
+
+    /// Drops expired entries, then the least recently used ones until we're
+    /// back under `max_entries`.
+    fn evict(&self) -> Result<()> {
+        let now = budget::now();
+        let mut entries = Vec::new();
+
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            let entry: Option<CacheEntry> = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok());
+
+            match entry {
+                Some(entry) if now.saturating_sub(entry.created_at) <= self.ttl_secs => {
+                    entries.push((entry.last_used, path));
+                }
+                // Expired or unreadable
+                _ => {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+
+        if entries.len() > self.max_entries {
+            entries.sort_by_key(|(last_used, _)| *last_used);
+            let excess = entries.len() - self.max_entries;
+            for (_, path) in entries.into_iter().take(excess) {
+                let _ = fs::remove_file(path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Short "how long ago" label for cached answers.
+pub fn describe_age(created_at: u64) -> String {
+    let secs = budget::now().saturating_sub(created_at);
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
@@ -0,0 +1,129 @@
+//! Ctrl-C handling. A single listener decides what an interrupt means: the
+//! first one while an answer is being typed out skips to the end of it,
+//! anything else abandons the request and exits with the usual 130. Any
+//! other key also skips the typing.
+
+use std::future::Future;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+static TYPING: AtomicBool = AtomicBool::new(false);
+static SKIP_TYPING: AtomicBool = AtomicBool::new(false);
+static IN_FLIGHT: AtomicBool = AtomicBool::new(false);
+static CANCEL: Notify = Notify::const_new();
+
+/// Takes over Ctrl-C for the rest of the run. Call it once the question is
+/// known, so the line editor and `$EDITOR` still get to handle their own.
+pub fn install() {
+    tokio::spawn(async {
+        loop {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            if TYPING.load(Ordering::SeqCst) && !SKIP_TYPING.swap(true, Ordering::SeqCst) {
+                continue;
+            }
+            if IN_FLIGHT.load(Ordering::SeqCst) {
+                CANCEL.notify_one();
+            } else {
+                exit();
+            }
+        }
+    });
+}
+
+/// Runs a request until it finishes or Ctrl-C drops it.
+pub async fn abortable<F: Future>(request: F) -> F::Output {
+    IN_FLIGHT.store(true, Ordering::SeqCst);
+    let result = tokio::select! {
+        result = request => Some(result),
+        _ = CANCEL.notified() => None,
+    };
+    IN_FLIGHT.store(false, Ordering::SeqCst);
+    result.unwrap_or_else(|| exit())
+}
+
+/// Marks the start or end of typed-out output, and watches for keys
+/// meanwhile.
+pub fn set_typing(typing: bool) {
+    SKIP_TYPING.store(false, Ordering::SeqCst);
+    TYPING.store(typing, Ordering::SeqCst);
+    keys::watch(typing);
+}
+
+/// Whether Ctrl-C or a key asked for the rest of the answer at once.
+pub fn skip_typing() -> bool {
+    if keys::pressed() {
+        SKIP_TYPING.store(true, Ordering::SeqCst);
+    }
+    SKIP_TYPING.load(Ordering::SeqCst)
+}
+
+/// Leaves the terminal as it found it and exits with 130.
+pub fn exit() -> ! {
+    keys::watch(false);
+    // Clear a spinner line, close any open style or link and bring the cursor back
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "\x1b[0m\x1b]8;;\x1b\\\x1b[?25h");
+    let _ = stdout.flush();
+    eprintln!("\r\x1b[2K\x1b[0m✋ Cancelled");
+    std::process::exit(130);
+}
+
+/// Reading single key presses without waiting for Enter or echoing them.
+#[cfg(unix)]
+mod keys {
+    use std::io::{self, IsTerminal};
+    use std::sync::Mutex;
+
+    // The terminal settings to put back, while keys are being watched
+    static SAVED: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+    pub fn watch(on: bool) {
+        let mut saved = SAVED.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(original) = saved.take() {
+            // SAFETY: restores settings read from the same descriptor
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original) };
+        }
+        if !on || !io::stdin().is_terminal() {
+            return;
+        }
+
+        // SAFETY: termios is plain data and tcgetattr fills it in
+        let mut settings: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut settings) } != 0 {
+            return;
+        }
+        let original = settings;
+        // Keep ISIG so Ctrl-C still arrives as a signal
+        settings.c_lflag &= !(libc::ICANON | libc::ECHO);
+        settings.c_cc[libc::VMIN] = 0;
+        settings.c_cc[libc::VTIME] = 0;
+        // SAFETY: settings came from tcgetattr on the same descriptor
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &settings) } == 0 {
+            *saved = Some(original);
+        }
+    }
+
+    /// Whether a key was pressed since the last call. Never blocks.
+    pub fn pressed() -> bool {
+        if SAVED.lock().map_or(true, |saved| saved.is_none()) {
+            return false;
+        }
+        let mut buf = [0u8; 32];
+        // SAFETY: reads into a local buffer of the given length; VMIN and
+        // VTIME of 0 make it return straight away
+        let read = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        read > 0
+    }
+}
+
+#[cfg(not(unix))]
+mod keys {
+    pub fn watch(_on: bool) {}
+
+    pub fn pressed() -> bool {
+        false
+    }
+}
@@ -0,0 +1,103 @@
+//! Copying answers with `--copy` and `--copy-code`.
+
+use anyhow::Result;
+use base64::Engine;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// The code of an answer: its fenced blocks, or its inline `code` spans if
+/// it has no blocks.
+pub fn code_blocks(answer: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+    for line in answer.lines() {
+        if line.trim_start().starts_with("```") {
+            match current.take() {
+                Some(lines) => blocks.push(lines.join("\n")),
+                None => current = Some(Vec::new()),
+            }
+        } else if let Some(lines) = &mut current {
+            lines.push(line);
+        }
+    }
+    if !blocks.is_empty() {
+        return blocks;
+    }
+
+    answer
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .filter(|span| !span.trim().is_empty() && !span.contains('\n'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Puts `text` on the clipboard and says how: the tool used, `file` or
+/// `terminal` for OSC 52. `$QQ_CLIPBOARD_FILE` gets the text instead when
+/// set, which is handy for scripts and tests. Over SSH the local terminal is
+/// asked to copy with OSC 52, since the remote machine's clipboard isn't the
+/// one you want.
+pub fn copy(text: &str) -> Result<&'static str> {
+    if let Ok(path) = std::env::var("QQ_CLIPBOARD_FILE") {
+        fs::write(path, text)?;
+        return Ok("file");
+    }
+    if std::env::var_os("SSH_TTY").is_some() || std::env::var_os("SSH_CONNECTION").is_some() {
+        return osc52(text);
+    }
+
+    let mut tools: Vec<(&str, &[&str])> = Vec::new();
+    if cfg!(target_os = "macos") {
+        tools.push(("pbcopy", &[]));
+    } else if cfg!(windows) {
+        tools.push(("clip", &[]));
+    } else {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            tools.push(("wl-copy", &[]));
+        }
+        if std::env::var_os("DISPLAY").is_some() {
+            tools.push(("xclip", &["-selection", "clipboard"]));
+            tools.push(("xsel", &["--clipboard", "--input"]));
+        }
+    }
+
+    for (tool, args) in tools {
+        if pipe_to(tool, args, text).is_ok() {
+            return Ok(tool);
+        }
+    }
+    osc52(text)
+}
+
+fn pipe_to(tool: &str, args: &[&str], text: &str) -> Result<()> {
+    let mut child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("{} exited with {}", tool, status));
+    }
+    Ok(())
+}
+
+/// Asks the terminal itself to set the clipboard.
+fn osc52(text: &str) -> Result<&'static str> {
+    let sequence = format!("\x1b]52;c;{}\x07", base64::engine::general_purpose::STANDARD.encode(text));
+    if io::stderr().is_terminal() {
+        eprint!("{}", sequence);
+        return Ok("terminal");
+    }
+    match fs::OpenOptions::new().write(true).open("/dev/tty") {
+        Ok(mut tty) => {
+            tty.write_all(sequence.as_bytes())?;
+            Ok("terminal")
+        }
+        Err(_) => Err(anyhow::anyhow!("No clipboard tool found (install wl-copy or xclip)")),
+    }
+}
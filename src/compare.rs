@@ -0,0 +1,241 @@
+//! `qq --compare`: one question to several providers and models at once,
+//! with the answers side by side and a record kept for later review.
+
+use anyhow::Result;
+use serde_json::json;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+use crate::budget;
+use crate::config::Config;
+use crate::prompt::Prompt;
+use crate::providers::{Answer, Provider, Target};
+use crate::render;
+
+// Narrower than this and the answers are shown one after another
+const MIN_PANE_WIDTH: usize = 32;
+const SEPARATOR: &str = " │ ";
+
+/// How one provider and model did.
+#[derive(Debug)]
+pub struct Outcome {
+    pub target: Target,
+    pub answer: Result<Answer, String>,
+    pub latency: Duration,
+}
+
+impl Outcome {
+    pub fn cost(&self) -> f64 {
+        self.answer
+            .as_ref()
+            .map_or(0.0, |answer| budget::cost_of(&self.target.model, &answer.usage))
+    }
+
+    /// Latency, tokens and cost on one line.
+    pub fn stats(&self) -> String {
+        let seconds = self.latency.as_secs_f64();
+        match &self.answer {
+            Ok(answer) => {
+                let mut stats = format!(
+                    "{:.1}s · {} in · {} out · ${:.4}",
+                    seconds,
+                    answer.usage.input_tokens,
+                    answer.usage.output_tokens,
+                    self.cost()
+                );
+                if answer.is_truncated() {
+                    stats.push_str(" · cut off");
+                }
+                stats
+            }
+            Err(_) => format!("failed after {:.1}s", seconds),
+        }
+    }
+}
+
+/// Asks every target at once and returns their outcomes in the order given.
+pub async fn run(prompt: Prompt, targets: Vec<(Target, Box<dyn Provider>)>) -> Vec<Outcome> {
+    let prompt = Arc::new(prompt);
+    let mut running = JoinSet::new();
+    let count = targets.len();
+    for (index, (target, provider)) in targets.into_iter().enumerate() {
+        let prompt = Arc::clone(&prompt);
+        running.spawn(async move {
+            let started = Instant::now();
+            let answer = provider.ask(&prompt).await.map_err(|e| e.to_string());
+            (index, Outcome { target, answer, latency: started.elapsed() })
+        });
+    }
+
+    let mut outcomes: Vec<Option<Outcome>> = (0..count).map(|_| None).collect();
+    while let Some(finished) = running.join_next().await {
+        if let Ok((index, outcome)) = finished {
+            outcomes[index] = Some(outcome);
+        }
+    }
+    outcomes.into_iter().flatten().collect()
+}
+
+/// The comparison as JSON, for `--json` and the saved record.
+pub fn record(question: &str, outcomes: &[Outcome], created_at: u64) -> serde_json::Value {
+    let results: Vec<serde_json::Value> = outcomes
+        .iter()
+        .map(|outcome| {
+            let answer = outcome.answer.as_ref().ok();
+            json!({
+                "provider": outcome.target.provider,
+                "model": outcome.target.model,
+                "answer": answer.map(|answer| &answer.text),
+                "error": outcome.answer.as_ref().err(),
+                "stop_reason": answer.and_then(|answer| answer.stop_reason.as_ref()),
+                "latency_ms": outcome.latency.as_millis() as u64,
+                "usage": answer.map(|answer| &answer.usage),
+                "cost_usd": answer.map(|_| outcome.cost()),
+            })
+        })
+        .collect();
+    json!({
+        "question": question,
+        "created_at": created_at,
+        "results": results,
+    })
+}
+
+/// Saves a record under the cache directory and returns where.
+pub fn save_record(record: &serde_json::Value, created_at: u64) -> Result<PathBuf> {
+    let dir = Config::cache_dir()?.join("comparisons");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}-{}.json", created_at, std::process::id()));
+    std::fs::write(&path, serde_json::to_string_pretty(record)?)?;
+    Ok(path)
+}
+
+/// Prints the answers in columns when they fit, one after another otherwise.
+pub fn print(outcomes: &[Outcome]) {
+    let width = io::stdout().is_terminal().then(terminal_width).flatten();
+    match width {
+        Some(width) if pane_width(width, outcomes.len()) >= MIN_PANE_WIDTH => {
+            let lines = panes(outcomes, width);
+            println!();
+            for (number, line) in lines.iter().enumerate() {
+                if number == 0 {
+                    println!("\x1b[1m{}\x1b[0m", line);
+                } else if number == lines.len() - 1 {
+                    println!("\x1b[2m{}\x1b[0m", line);
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+        _ => {
+            for outcome in outcomes {
+                println!("\n\x1b[1m── {} ──\x1b[0m", outcome.target.label());
+                match &outcome.answer {
+                    Ok(answer) => println!("{}", render::format_for_terminal(answer.text.trim())),
+                    Err(e) => println!("\x1b[31m❌ {}\x1b[0m", e),
+                }
+                println!("\x1b[2m{}\x1b[0m", outcome.stats());
+            }
+        }
+    }
+}
+
+fn pane_width(width: usize, panes: usize) -> usize {
+    width.saturating_sub(SEPARATOR.chars().count() * panes.saturating_sub(1)) / panes.max(1)
+}
+
+/// The answers as plain text columns across `width`: a label, a rule, the
+/// wrapped answer and the stats, which line up along the bottom.
+pub fn panes(outcomes: &[Outcome], width: usize) -> Vec<String> {
+    let pane_width = pane_width(width, outcomes.len());
+    let bodies: Vec<Vec<String>> = outcomes
+        .iter()
+        .map(|outcome| match &outcome.answer {
+            Ok(answer) => wrap(&render::plain_text(&answer.text), pane_width),
+            Err(e) => wrap(&format!("Failed: {}", e), pane_width),
+        })
+        .collect();
+    let height = bodies.iter().map(Vec::len).max().unwrap_or(0);
+
+    let row = |cells: Vec<String>| {
+        let padded: Vec<String> = cells.iter().map(|cell| pad(cell, pane_width)).collect();
+        padded.join(SEPARATOR).trim_end().to_string()
+    };
+    let rule = "─".repeat(pane_width);
+
+    let mut lines = vec![row(outcomes.iter().map(|outcome| truncate(&outcome.target.label(), pane_width)).collect())];
+    lines.push(row(outcomes.iter().map(|_| rule.clone()).collect()));
+    for line in 0..height {
+        lines.push(row(bodies.iter().map(|body| body.get(line).cloned().unwrap_or_default()).collect()));
+    }
+    lines.push(row(outcomes.iter().map(|_| rule.clone()).collect()));
+    lines.push(row(outcomes.iter().map(|outcome| truncate(&outcome.stats(), pane_width)).collect()));
+    lines
+}
+
+/// Word-wraps each line of `text` to `width` characters, keeping its
+/// indentation and breaking words that are longer than a whole line.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(2);
+    let mut lines = Vec::new();
+    for source in text.lines() {
+        let indent = " ".repeat((source.len() - source.trim_start_matches(' ').len()).min(width / 2));
+        let mut line = indent.clone();
+        let mut empty = true;
+        for word in source.split_whitespace() {
+            let mut chars: Vec<char> = word.chars().collect();
+            loop {
+                let used = line.chars().count() + usize::from(!empty);
+                let room = width.saturating_sub(used);
+                if chars.len() <= room {
+                    if !empty {
+                        line.push(' ');
+                    }
+                    line.extend(chars);
+                    empty = false;
+                    break;
+                }
+                if empty {
+                    line.extend(chars.drain(..room));
+                }
+                lines.push(std::mem::replace(&mut line, indent.clone()));
+                empty = true;
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    format!("{}{}", text, " ".repeat(width.saturating_sub(len)))
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(width.saturating_sub(1)).collect();
+    short.push('…');
+    short
+}
+
+/// Columns of the terminal, from `$COLUMNS` or asking the terminal.
+fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()) {
+        return Some(columns);
+    }
+    #[cfg(unix)]
+    {
+        // SAFETY: winsize is plain data that TIOCGWINSZ fills in
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+            return Some(size.ws_col as usize);
+        }
+    }
+    None
+}
@@ -0,0 +1,99 @@
+use clap_complete::Shell;
+
+use crate::config::Config;
+use crate::prompt;
+use crate::providers::claude;
+use crate::template::Template;
+
+/// Flags whose values are names of things in the config dir. The completion
+/// scripts run `QQ_COMPLETE=<kind> qq` at tab time to list them, so new ones
+/// show up without regenerating the script.
+const DYNAMIC: &[(Option<char>, &str, &str)] = &[
+    (Some('t'), "template", "templates"),
+    (None, "as", "personas"),
+    (None, "compare", "models"),
+];
+
+/// Values for a dynamic flag, one per line when printed.
+pub fn candidates(kind: &str) -> Vec<String> {
+    match kind {
+        "templates" => Template::names(),
+        "personas" => prompt::persona_names(&Config::load().unwrap_or_default()),
+        "models" => claude::model_aliases()
+            .flat_map(|alias| [alias.to_string(), format!("claude:{}", alias)])
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The clap generated completion script, with dynamic values wired in for
+/// bash, zsh and fish.
+pub fn script(shell: Shell, cmd: &mut clap::Command) -> String {
+    let mut out = Vec::new();
+    clap_complete::generate(shell, cmd, "qq", &mut out);
+    let script = String::from_utf8_lossy(&out).into_owned();
+
+    match shell {
+        Shell::Bash => script + &bash_dynamic(),
+        Shell::Zsh => zsh_dynamic(&script),
+        Shell::Fish => script + &fish_dynamic(),
+        _ => script,
+    }
+}
+
+fn bash_dynamic() -> String {
+    let cases: String = DYNAMIC
+        .iter()
+        .map(|(short, long, kind)| match short {
+            Some(short) => format!("        -{}|--{}) kind={} ;;\n", short, long, kind),
+            None => format!("        --{}) kind={} ;;\n", long, kind),
+        })
+        .collect();
+    format!(
+        r#"
+_qq_dynamic() {{
+    local kind
+    case "$3" in
+{}        *) _qq "$@"; return ;;
+    esac
+    COMPREPLY=($(compgen -W "$(QQ_COMPLETE=$kind command qq 2>/dev/null)" -- "$2"))
+}}
+complete -F _qq_dynamic -o bashdefault -o default qq
+"#,
+        cases
+    )
+}
+
+fn zsh_dynamic(script: &str) -> String {
+    script
+        .lines()
+        .map(|line| {
+            let dynamic = DYNAMIC.iter().find(|(short, long, _)| {
+                short.is_some_and(|short| line.contains(&format!("-{}+[", short)))
+                    || line.contains(&format!("--{}=[", long))
+            });
+            match dynamic {
+                Some((_, _, kind)) => line.replace(
+                    ":_default'",
+                    &format!(":{{compadd -- ${{(f)\"$(QQ_COMPLETE={} command qq 2>/dev/null)\"}}}}'", kind),
+                ),
+                None => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+fn fish_dynamic() -> String {
+    DYNAMIC
+        .iter()
+        .map(|(short, long, kind)| {
+            let short = short.map(|short| format!("-s {} ", short)).unwrap_or_default();
+            format!(
+                "complete -c qq -n \"__fish_qq_needs_command\" {}-l {} -x -a \"(env QQ_COMPLETE={} qq 2>/dev/null)\"\n",
+                short, long, kind
+            )
+        })
+        .collect()
+}
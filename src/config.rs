@@ -1,33 +1,184 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 use anyhow::Result;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
+    /// Which backend answers questions: "claude" (default) or "mock"
+    #[serde(default = "default_provider")]
+    pub provider: String,
+    #[serde(default)]
     pub claude_api_key: String,
+    /// Overrides https://api.anthropic.com, e.g. for a proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claude_base_url: Option<String>,
     pub model: String,
     pub max_tokens: u32,
+    /// Providers to try in turn when `provider` is down, as "provider:model"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback: Vec<String>,
+    /// Extended thinking budget in tokens, off when unset
+    #[serde(default)]
+    pub thinking_budget: Option<u32>,
+    #[serde(default)]
+    pub budget: BudgetConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Named system prompts for `--as`, on top of the built-in ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub personas: BTreeMap<String, Persona>,
+    /// Offer to fix questions whose apostrophes the shell swallowed
+    #[serde(default = "default_check_mangling")]
+    pub check_mangling: bool,
+    /// How answers are typed out: "off", "fast" or "natural"
+    #[serde(default)]
+    pub typing: TypingMode,
+    /// The typing effect speeds up so no answer takes longer than this
+    #[serde(default = "default_typing_max_secs")]
+    pub typing_max_secs: f64,
+    /// Attached images are scaled down to fit this many pixels, 0 to send as is
+    #[serde(default = "default_image_max_edge")]
+    pub image_max_edge: u32,
+}
+
+/// Pace of the typing effect. Output that isn't a terminal is never typed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TypingMode {
+    /// Print the answer at once
+    Off,
+    /// A steady, quick pace
+    #[default]
+    Fast,
+    /// Slower, with pauses after sentences and lines
+    Natural,
+}
+
+/// Optional spending and rate limits, all unset by default.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BudgetConfig {
+    pub daily_usd: Option<f64>,
+    pub monthly_usd: Option<f64>,
+    pub max_requests_per_hour: Option<u32>,
+}
+
+/// Local answer cache settings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub ttl_hours: u64,
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_hours: 24 * 7,
+            max_entries: 500,
+        }
+    }
+}
+
+/// A `[personas.<name>]` table: a system prompt to answer with, replacing
+/// the default one unless `extend` is set.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Persona {
+    pub prompt: String,
+    /// Add the prompt after the default one instead of replacing it
+    pub extend: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl Persona {
+    /// Applies the persona's model and max_tokens on top of the config.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(model) = &self.model {
+            config.model = model.clone();
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            config.max_tokens = max_tokens;
+        }
+    }
+}
+
+fn default_provider() -> String {
+    "claude".to_string()
+}
+
+fn default_check_mangling() -> bool {
+    true
+}
+
+pub(crate) fn default_typing_max_secs() -> f64 {
+    2.0
+}
+
+fn default_image_max_edge() -> u32 {
+    1568
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            provider: default_provider(),
             claude_api_key: String::new(),
+            claude_base_url: None,
+            fallback: Vec::new(),
             model: "claude-sonnet-4-20250514".to_string(),
             max_tokens: 300,
+            thinking_budget: None,
+            budget: BudgetConfig::default(),
+            cache: CacheConfig::default(),
+            personas: BTreeMap::new(),
+            check_mangling: true,
+            typing: TypingMode::default(),
+            typing_max_secs: default_typing_max_secs(),
+            image_max_edge: default_image_max_edge(),
         }
     }
 }
 
 impl Config {
     pub fn config_dir() -> Result<PathBuf> {
+        // Lets tests and multiple setups point qq at a different directory
+        if let Ok(dir) = std::env::var("QQ_CONFIG_DIR") {
+            return Ok(PathBuf::from(dir));
+        }
+        
         let mut path = dirs::config_dir()
             .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
         path.push("quick-question");
         Ok(path)
     }
     
+    pub fn cache_dir() -> Result<PathBuf> {
+        // Keep everything together when the config dir is overridden
+        if let Ok(dir) = std::env::var("QQ_CONFIG_DIR") {
+            return Ok(PathBuf::from(dir).join("cache"));
+        }
+        
+        // Fall back to the config directory on platforms without a cache dir
+        match dirs::cache_dir() {
+            Some(mut path) => {
+                path.push("quick-question");
+                Ok(path)
+            }
+            None => {
+                let mut path = Self::config_dir()?;
+                path.push("cache");
+                Ok(path)
+            }
+        }
+    }
+    
     pub fn config_path() -> Result<PathBuf> {
         let mut path = Self::config_dir()?;
         path.push("config.toml");
@@ -40,6 +191,12 @@ impl Config {
         Ok(path)
     }
     
+    pub fn templates_dir() -> Result<PathBuf> {
+        let mut path = Self::config_dir()?;
+        path.push("templates");
+        Ok(path)
+    }
+    
     pub fn load() -> Result<Self> {
         let path = Self::config_path()?;
         
@@ -54,6 +211,12 @@ impl Config {
         if let Ok(api_key) = std::env::var("CLAUDE_API_KEY") {
             config.claude_api_key = api_key;
         }
+        if let Ok(base_url) = std::env::var("ANTHROPIC_BASE_URL") {
+            config.claude_base_url = Some(base_url);
+        }
+        if let Ok(provider) = std::env::var("QQ_PROVIDER") {
+            config.provider = provider;
+        }
         
         Ok(config)
     }
@@ -0,0 +1,296 @@
+//! Documents attached with `-f`: PDFs, Word files, HTML pages and plain
+//! text, optionally cut down to a page range with `-f spec.pdf:3-7`.
+
+use anyhow::Result;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Pages to include, counting from 1. `last` is open-ended when unset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRange {
+    pub first: u32,
+    pub last: Option<u32>,
+}
+
+impl PageRange {
+    /// `3`, `3-7` or `3-`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (first, last) = match text.split_once('-') {
+            Some((first, "")) => (first.parse().ok()?, None),
+            Some((first, last)) => (first.parse().ok()?, Some(last.parse().ok()?)),
+            None => {
+                let page = text.parse().ok()?;
+                (page, Some(page))
+            }
+        };
+        if first == 0 || last.is_some_and(|last| last < first) {
+            return None;
+        }
+        Some(Self { first, last })
+    }
+}
+
+impl std::fmt::Display for PageRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.last {
+            Some(last) if last == self.first => write!(f, "page {}", last),
+            Some(last) => write!(f, "pages {}-{}", self.first, last),
+            None => write!(f, "pages {} to the end", self.first),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Content {
+    Text(String),
+    /// Sent as is, for providers that read PDFs themselves
+    Pdf(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    /// The file name, shown to the model as the document title
+    pub name: String,
+    pub pages: Option<PageRange>,
+    pub content: Content,
+}
+
+/// Splits `spec.pdf:3-7` into the path and page range. A colon that isn't
+/// followed by a range is part of the path.
+pub fn parse_spec(spec: &str) -> (PathBuf, Option<PageRange>) {
+    if let Some((path, range)) = spec.rsplit_once(':') {
+        if let Some(range) = PageRange::parse(range) {
+            return (PathBuf::from(path), Some(range));
+        }
+    }
+    (PathBuf::from(spec), None)
+}
+
+impl Document {
+    /// Reads the file named by `spec` and turns it into text, except for
+    /// whole PDFs when `native_pdf` says the provider can take them directly.
+    pub fn load(spec: &str, native_pdf: bool) -> Result<Self> {
+        let (path, pages) = parse_spec(spec);
+        let data = fs::read(&path).map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+        let name = path
+            .file_name()
+            .map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let content = if data.starts_with(b"%PDF-") {
+            if native_pdf && pages.is_none() {
+                Content::Pdf(data)
+            } else {
+                Content::Text(pdf_text(&path, pages)?)
+            }
+        } else if pages.is_some() {
+            return Err(anyhow::anyhow!("Page ranges only work for PDFs, not {}", name));
+        } else if extension == "docx" {
+            Content::Text(docx_text(&path)?)
+        } else {
+            let text = String::from_utf8(data).map_err(|_| {
+                anyhow::anyhow!("{} isn't a PDF, Word document, HTML page or text file", name)
+            })?;
+            if extension == "html" || extension == "htm" || looks_like_html(&text) {
+                Content::Text(html_text(&text))
+            } else {
+                Content::Text(text)
+            }
+        };
+
+        Ok(Self { name, pages, content })
+    }
+
+    /// The title the model sees, with the pages when only some were sent.
+    pub fn title(&self) -> String {
+        match self.pages {
+            Some(pages) => format!("{} ({} only)", self.name, pages),
+            None => self.name.clone(),
+        }
+    }
+
+    pub fn base64(&self) -> String {
+        match &self.content {
+            Content::Text(text) => base64::engine::general_purpose::STANDARD.encode(text),
+            Content::Pdf(data) => base64::engine::general_purpose::STANDARD.encode(data),
+        }
+    }
+
+    /// Roughly what the document costs in input tokens. PDFs are read as
+    /// text and as an image of each page, so they cost more per page.
+    pub fn estimated_tokens(&self) -> u32 {
+        match &self.content {
+            Content::Text(text) => text.len().div_ceil(4) as u32,
+            Content::Pdf(data) => pdf_page_count(data).max(1) * 2500,
+        }
+    }
+
+    /// Hex SHA-256 of the content, to tell attachments apart in the cache.
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.title().as_bytes());
+        match &self.content {
+            Content::Text(text) => hasher.update(text.as_bytes()),
+            Content::Pdf(data) => hasher.update(data),
+        }
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Counts `/Type /Page` objects, which is close enough for an estimate.
+fn pdf_page_count(data: &[u8]) -> u32 {
+    let mut count = 0;
+    for (i, window) in data.windows(5).enumerate() {
+        if window == b"/Page" && data.get(i + 5) != Some(&b's') {
+            let before = &data[i.saturating_sub(7)..i];
+            if before.ends_with(b"/Type ") || before.ends_with(b"/Type") {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// Text of a PDF, or of some of its pages, using poppler's `pdftotext`.
+fn pdf_text(path: &Path, pages: Option<PageRange>) -> Result<String> {
+    let mut command = Command::new("pdftotext");
+    command.arg("-layout");
+    if let Some(pages) = pages {
+        command.args(["-f", &pages.first.to_string()]);
+        if let Some(last) = pages.last {
+            command.args(["-l", &last.to_string()]);
+        }
+    }
+    command.arg(path).arg("-");
+
+    let output = command
+        .output()
+        .map_err(|_| anyhow::anyhow!("Reading {} needs pdftotext (from poppler-utils)", path.display()))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "pdftotext couldn't read {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Text of a Word document. DOCX files are zip archives, so this borrows
+/// `unzip`, or the `tar` that ships with macOS and Windows.
+fn docx_text(path: &Path) -> Result<String> {
+    let attempts: [(&str, &[&str]); 2] = [("unzip", &["-p"]), ("tar", &["-xOf"])];
+    let xml = attempts
+        .iter()
+        .find_map(|(tool, args)| {
+            let output = Command::new(tool).args(*args).arg(path).arg("word/document.xml").output().ok()?;
+            (output.status.success() && !output.stdout.is_empty()).then_some(output.stdout)
+        })
+        .ok_or_else(|| anyhow::anyhow!("Couldn't unpack {} (is unzip installed?)", path.display()))?;
+    Ok(docx_xml_text(&String::from_utf8_lossy(&xml)))
+}
+
+/// The text runs of `word/document.xml`, a paragraph per line.
+pub fn docx_xml_text(xml: &str) -> String {
+    let mut text = String::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        let Some(len) = rest[start..].find('>') else { break };
+        let tag = &rest[start + 1..start + len];
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        rest = &rest[start + len + 1..];
+
+        match name {
+            "w:t" => {
+                let end = rest.find("</w:t>").unwrap_or(rest.len());
+                text.push_str(&decode_entities(&rest[..end]));
+                rest = &rest[end..];
+            }
+            "w:tab/" => text.push('\t'),
+            "w:br/" | "/w:p" => text.push('\n'),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+fn looks_like_html(text: &str) -> bool {
+    let start = text.trim_start().get(..15).unwrap_or_default().to_lowercase();
+    start.starts_with("<!doctype html") || start.starts_with("<html")
+}
+
+/// Readable text of an HTML page: no tags, scripts or styles, one line per
+/// block element.
+pub fn html_text(html: &str) -> String {
+    const BLOCKS: &[&str] = &[
+        "p", "div", "br", "li", "tr", "h1", "h2", "h3", "h4", "h5", "h6", "pre", "section", "article", "table",
+    ];
+
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..start]));
+        let Some(len) = rest[start..].find('>') else { break };
+        let tag = rest[start + 1..start + len].to_lowercase();
+        rest = &rest[start + len + 1..];
+
+        let name = tag.trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+        if (name == "script" || name == "style") && !tag.starts_with('/') {
+            let close = format!("</{}", name);
+            let end = rest.to_lowercase().find(&close).unwrap_or(rest.len());
+            rest = &rest[end..];
+        } else if BLOCKS.contains(&name) {
+            text.push('\n');
+        }
+    }
+    text.push_str(&decode_entities(rest));
+
+    // Collapse the source's indentation and blank runs
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').filter(|end| *end <= 10).map(|end| &rest[1..end]);
+        let replacement = match entity {
+            Some("amp") => Some('&'),
+            Some("lt") => Some('<'),
+            Some("gt") => Some('>'),
+            Some("quot") => Some('"'),
+            Some("apos") | Some("#39") => Some('\''),
+            Some("nbsp") => Some(' '),
+            Some(code) if code.starts_with("#x") => u32::from_str_radix(&code[2..], 16).ok().and_then(char::from_u32),
+            Some(code) if code.starts_with('#') => code[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+        match (replacement, entity) {
+            (Some(c), Some(entity)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
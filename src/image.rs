@@ -0,0 +1,197 @@
+//! Images attached with `-i`, checked and if need be shrunk before they're
+//! sent along with the question.
+
+use anyhow::Result;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+// Anthropic rejects anything bigger than this
+const MAX_BYTES: usize = 5 * 1024 * 1024;
+const MAX_EDGE: u32 = 8000;
+
+/// An image file ready to attach to a question.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub path: PathBuf,
+    /// "image/png", "image/jpeg", "image/gif" or "image/webp"
+    pub media_type: &'static str,
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Image {
+    /// Reads and checks an image. When its longer edge is over `max_edge`
+    /// pixels (0 for no limit) or the file is too big to send, it's scaled
+    /// down with ImageMagick or `sips` if either is installed.
+    pub fn load(path: &Path, max_edge: u32) -> Result<Self> {
+        let data = fs::read(path).map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+        let mut image = Self::from_bytes(path, data)?;
+
+        let longest = image.width.max(image.height);
+        let too_wide = (max_edge > 0 && longest > max_edge) || longest > MAX_EDGE;
+        if too_wide || image.data.len() > MAX_BYTES {
+            let edge = if max_edge > 0 { max_edge.min(MAX_EDGE) } else { MAX_EDGE };
+            match downscale(path, image.media_type, edge) {
+                Some(data) => image = Self::from_bytes(path, data)?,
+                // The API scales large images itself, as long as they fit its limits
+                None if image.data.len() <= MAX_BYTES && longest <= MAX_EDGE => {}
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "{} is too large to send ({}x{}, {} KB) and couldn't be scaled down; install ImageMagick or shrink it first",
+                        path.display(),
+                        image.width,
+                        image.height,
+                        image.data.len() / 1024
+                    ))
+                }
+            }
+        }
+
+        if image.data.len() > MAX_BYTES {
+            return Err(anyhow::anyhow!("{} is over the 5 MB limit for images", path.display()));
+        }
+        if image.width.max(image.height) > MAX_EDGE {
+            return Err(anyhow::anyhow!(
+                "{} is {}x{}, images can be at most {} pixels on a side",
+                path.display(),
+                image.width,
+                image.height,
+                MAX_EDGE
+            ));
+        }
+        Ok(image)
+    }
+
+    /// Works out the format from the file's contents, whatever its name.
+    pub fn from_bytes(path: &Path, data: Vec<u8>) -> Result<Self> {
+        let (media_type, (width, height)) = media_type(&data)
+            .and_then(|media_type| Some((media_type, dimensions(media_type, &data)?)))
+            .ok_or_else(|| anyhow::anyhow!("{} isn't a PNG, JPEG, GIF or WebP image", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            media_type,
+            data,
+            width,
+            height,
+        })
+    }
+
+    pub fn base64(&self) -> String {
+        base64::engine::general_purpose::STANDARD.encode(&self.data)
+    }
+
+    /// Roughly what the image costs in input tokens, after the API scales
+    /// it to fit 1568 pixels.
+    pub fn estimated_tokens(&self) -> u32 {
+        let scale = (1568.0 / self.width.max(self.height) as f64).min(1.0);
+        let pixels = self.width as f64 * scale * self.height as f64 * scale;
+        (pixels / 750.0).ceil() as u32
+    }
+
+    /// Hex SHA-256 of the image data, to tell attachments apart in the cache.
+    pub fn digest(&self) -> String {
+        Sha256::digest(&self.data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+fn media_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// Width and height from the image header.
+fn dimensions(media_type: &str, data: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le16 = |at: usize| Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le24 = |at: usize| {
+        let bytes = data.get(at..at + 3)?;
+        Some(bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16)
+    };
+
+    match media_type {
+        "image/png" => {
+            let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+            let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+            Some((width, height))
+        }
+        "image/gif" => Some((le16(6)?, le16(8)?)),
+        "image/jpeg" => {
+            // Walk the segments up to the start-of-frame that holds the size
+            let mut at = 2;
+            loop {
+                if *data.get(at)? != 0xFF {
+                    return None;
+                }
+                let marker = *data.get(at + 1)?;
+                if marker == 0xFF {
+                    at += 1;
+                    continue;
+                }
+                if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+                    return Some((be16(at + 7)?, be16(at + 5)?));
+                }
+                at += 2 + be16(at + 2)? as usize;
+            }
+        }
+        "image/webp" => match data.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3FFF, le16(28)? & 0x3FFF)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A copy of the image scaled to fit `edge` pixels, using whichever of
+/// ImageMagick or macOS's `sips` is around.
+fn downscale(path: &Path, media_type: &str, edge: u32) -> Option<Vec<u8>> {
+    let extension = media_type.trim_start_matches("image/");
+    // A fresh file of our own, so the resizer can't be made to write
+    // through a symlink someone planted in a shared /tmp. Removed on drop.
+    let out_file = tempfile::Builder::new()
+        .prefix("qq-image-")
+        .suffix(&format!(".{}", extension))
+        .tempfile()
+        .ok()?;
+    let out = out_file.path();
+    let size = format!("{}x{}>", edge, edge);
+    let edge = edge.to_string();
+
+    let tools: [(&str, Vec<&std::ffi::OsStr>); 3] = [
+        ("magick", vec![path.as_os_str(), "-resize".as_ref(), size.as_ref(), out.as_os_str()]),
+        ("convert", vec![path.as_os_str(), "-resize".as_ref(), size.as_ref(), out.as_os_str()]),
+        ("sips", vec!["-Z".as_ref(), edge.as_ref(), path.as_os_str(), "--out".as_ref(), out.as_os_str()]),
+    ];
+    let scaled = tools.iter().find_map(|(tool, args)| {
+        // Windows has an unrelated convert.exe for file systems
+        if cfg!(windows) && *tool == "convert" {
+            return None;
+        }
+        let status = Command::new(tool)
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .ok()?;
+        status.success().then(|| fs::read(out).ok()).flatten()
+    });
+    drop(out_file);
+    scaled
+}
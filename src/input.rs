@@ -0,0 +1,336 @@
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{
+    Cmd, CompletionType, ConditionalEventHandler, Context, Editor, Event, EventContext, EventHandler, Helper, KeyEvent,
+    RepeatCount,
+};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::process::Command;
+
+use crate::config::Config;
+use crate::mangling;
+
+/// Works out the question from the command line arguments, piped stdin or,
+/// failing both, by asking for it interactively. `check_mangling` offers a
+/// fixed up question when the shell looks to have eaten apostrophes.
+pub fn read_question(args: &[String], check_mangling: bool) -> anyhow::Result<String> {
+    let question = if args.is_empty() {
+        // Check if we have stdin input (piped)
+        if !IsTerminal::is_terminal(&io::stdin()) {
+            // Read from stdin (pipe or redirection)
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input.trim().to_string()
+        } else {
+            // No arguments and no pipe, go interactive
+            get_question_interactively()?
+        }
+    } else {
+        let joined = args.join(" ");
+        match mangling::detect(args) {
+            Some(suggestion) if check_mangling && io::stdin().is_terminal() => {
+                confirm_unmangled(&joined, &suggestion)?
+            }
+            _ => joined,
+        }
+    };
+    
+    Ok(question)
+}
+
+/// Shows the reconstructed question and lets the user take it, keep what
+/// they typed or fix it up by hand.
+fn confirm_unmangled(typed: &str, suggestion: &str) -> anyhow::Result<String> {
+    println!("🤔 The shell may have swallowed some apostrophes.");
+    println!("Did you mean: {}", suggestion);
+    print!("[Y]es / [n]o, ask as typed / [e]dit: ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let question = match answer.trim().to_lowercase().as_str() {
+        "n" | "no" => typed.to_string(),
+        "e" | "edit" => edit_question("> ", suggestion)?,
+        _ => suggestion.to_string(),
+    };
+    println!("💡 Tip: Put quotes around questions with apostrophes: qq \"what's up?\"");
+    Ok(question)
+}
+
+pub fn get_question_interactively() -> anyhow::Result<String> {
+    // Interactive terminal input only
+    println!("❓ Enter your question (Enter on an empty line sends it, /help for commands):");
+    io::stdout().flush()?;
+    
+    edit_question("> ", "")
+}
+
+// Commands understood at the interactive prompt
+const SLASH_COMMANDS: &[(&str, &str)] = &[
+    ("/edit", "write the question in your editor"),
+    ("/help", "show these commands"),
+    ("/quit", "leave without asking"),
+];
+
+/// A command typed at the interactive prompt instead of a question.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlashCommand {
+    Edit,
+    Help,
+    Quit,
+}
+
+impl SlashCommand {
+    /// The command `line` is, if it's exactly one of [`SLASH_COMMANDS`].
+    pub fn parse(line: &str) -> Option<Self> {
+        match line.trim() {
+            "/edit" => Some(Self::Edit),
+            "/help" => Some(Self::Help),
+            "/quit" => Some(Self::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Whether Enter should send `input` rather than start a new line. Pasted
+/// text arrives in one piece, so only an empty last line sends, besides
+/// empty input and commands.
+pub fn is_complete(input: &str) -> bool {
+    input.trim().is_empty() || input.ends_with('\n') || input.starts_with('/')
+}
+
+/// Whether Ctrl-D should send `input` rather than delete a character. On
+/// nothing at all it still ends input, as usual.
+pub fn ctrl_d_sends(input: &str) -> bool {
+    !input.is_empty()
+}
+
+/// Reads a question with line editing, Ctrl-R search and the history of
+/// earlier questions. Falls back to plain reading when there's no usable
+/// terminal. The line starts out as `initial`.
+fn edit_question(prompt: &str, initial: &str) -> anyhow::Result<String> {
+    let config = rustyline::Config::builder()
+        .history_ignore_dups(true)?
+        .max_history_size(1000)?
+        .completion_type(CompletionType::List)
+        .build();
+    let Ok(mut editor) = Editor::<QuestionHelper, FileHistory>::with_config(config) else {
+        return Ok(read_until_blank_line(io::stdin().lock())?);
+    };
+    editor.set_helper(Some(QuestionHelper::default()));
+    editor.bind_sequence(KeyEvent::ctrl('D'), EventHandler::Conditional(Box::new(CtrlDSends)));
+
+    let history = Config::config_dir().map(|dir| dir.join("input_history.txt"));
+    if let Ok(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        let question = match editor.readline_with_initial(prompt, (initial, "")) {
+            Ok(line) => line.trim().to_string(),
+            Err(ReadlineError::Eof) => return Ok(String::new()),
+            Err(ReadlineError::Interrupted) => std::process::exit(130),
+            Err(e) => return Err(e.into()),
+        };
+
+        match SlashCommand::parse(&question) {
+            Some(SlashCommand::Help) => {
+                for (command, description) in SLASH_COMMANDS {
+                    println!("  {:<8} {}", command, description);
+                }
+                continue;
+            }
+            Some(SlashCommand::Quit) => return Ok(String::new()),
+            Some(SlashCommand::Edit) => return read_from_editor(""),
+            None => {}
+        }
+
+        if !question.is_empty() {
+            let _ = editor.add_history_entry(question.as_str());
+            if let Ok(history) = &history {
+                let _ = fs::create_dir_all(history.parent().unwrap_or(history));
+                let _ = editor.save_history(history);
+            }
+        }
+        return Ok(question);
+    }
+}
+
+/// Sends a multi-line question on Ctrl-D, like the end of piped input.
+struct CtrlDSends;
+
+impl ConditionalEventHandler for CtrlDSends {
+    fn handle(&self, _: &Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        ctrl_d_sends(ctx.line()).then_some(Cmd::AcceptLine)
+    }
+}
+
+/// Line editor behaviour for the interactive prompt: Enter adds a line until
+/// an empty one, Tab completes slash commands and file paths.
+#[derive(Default)]
+struct QuestionHelper {
+    files: FilenameCompleter,
+}
+
+impl Completer for QuestionHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        if before.starts_with('/') && !before.contains(char::is_whitespace) {
+            let commands = SLASH_COMMANDS
+                .iter()
+                .filter(|(command, _)| command.starts_with(before))
+                .map(|(command, _)| Pair {
+                    display: command.to_string(),
+                    replacement: command.to_string(),
+                })
+                .collect();
+            return Ok((0, commands));
+        }
+
+        // Only words that look like paths, so prose doesn't list the cwd
+        let word = before.rsplit(char::is_whitespace).next().unwrap_or("");
+        if word.contains('/') || word.starts_with('~') || word.starts_with('.') {
+            return self.files.complete_path(line, pos);
+        }
+        Ok((pos, Vec::new()))
+    }
+}
+
+impl Validator for QuestionHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if is_complete(ctx.input()) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
+    }
+}
+
+impl Hinter for QuestionHelper {
+    type Hint = String;
+}
+
+impl Highlighter for QuestionHelper {}
+
+impl Helper for QuestionHelper {}
+
+/// Reads lines until an empty line or end of input, so pasted snippets
+/// don't get cut off at the first newline. Blank lines before any text are
+/// skipped.
+pub fn read_until_blank_line(mut reader: impl BufRead) -> io::Result<String> {
+    let mut text = String::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            if text.is_empty() {
+                continue;
+            }
+            break;
+        }
+        text.push_str(&line);
+    }
+    Ok(text.trim_end().to_string())
+}
+
+// Everything from this line down is qq's, not part of the question
+const EDITOR_MARKER: &str = "# ------------------------ >8 ------------------------";
+
+const EDITOR_INSTRUCTIONS: &str = "# Write your question above the line, then save and quit to send it.
+# The line and everything below it are left out. Leave it empty to cancel.
+";
+
+/// Lets the user write the question in `$VISUAL` or `$EDITOR`, starting
+/// from `initial`. Returns the saved text without qq's instructions.
+pub fn read_from_editor(initial: &str) -> anyhow::Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad".to_string() } else { "vi".to_string() });
+
+    let mut content = initial.trim().to_string();
+    content.push_str(if content.is_empty() { "\n\n" } else { "\n\n\n" });
+    content.push_str(EDITOR_MARKER);
+    content.push('\n');
+    content.push_str(EDITOR_INSTRUCTIONS);
+    // A fresh file only we can read, so nobody else on a shared /tmp can
+    // read the question or plant a symlink in its place. Removed on drop.
+    let mut file = tempfile::Builder::new().prefix("qq-question-").suffix(".md").tempfile()?;
+    file.write_all(content.as_bytes())?;
+    file.flush()?;
+    let path = file.path().to_path_buf();
+
+    // Editors like "code --wait" come with arguments
+    let mut parts = editor.split_whitespace();
+    let mut command = Command::new(parts.next().unwrap_or("vi"));
+    command.args(parts).arg(&path);
+
+    // Piped input used up stdin, so give the editor the terminal instead
+    if !io::stdin().is_terminal() {
+        if let Ok(tty) = fs::File::open("/dev/tty") {
+            command.stdin(tty);
+        }
+    }
+
+    let status = command.status();
+    // Read by path, since some editors save by replacing the file
+    let edited = fs::read_to_string(&path);
+    drop(file);
+
+    match status {
+        Ok(status) if status.success() => Ok(strip_instructions(&edited?)),
+        Ok(status) => Err(anyhow::anyhow!("Editor '{}' exited with {}", editor, status)),
+        Err(e) => Err(anyhow::anyhow!("Could not start editor '{}': {}", editor, e)),
+    }
+}
+
+/// Cuts the instructions qq added from the edited text and trims the rest.
+/// Anything the user wrote, `#` lines included, is kept.
+pub fn strip_instructions(text: &str) -> String {
+    let question = match text.lines().position(|line| line.trim_end() == EDITOR_MARKER) {
+        Some(marker) => text.lines().take(marker).collect::<Vec<_>>().join("\n"),
+        // The marker was deleted, so just leave out the instruction lines
+        None => text
+            .lines()
+            .filter(|line| !EDITOR_INSTRUCTIONS.lines().any(|instruction| instruction == *line))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    question.trim().to_string()
+}
+
+pub fn confirm_over_budget() -> anyhow::Result<bool> {
+    // Scripts and pipes never get to override the limits
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        eprintln!("Refusing to send the request. Adjust [budget] in your config to change the limits.");
+        return Ok(false);
+    }
+
+    confirm("Send it anyway?")
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no. Always no when
+/// there's nobody at the terminal to answer.
+pub fn confirm(prompt: &str) -> anyhow::Result<bool> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Ok(false);
+    }
+
+    print!("{} [y/N]: ", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,25 @@
+//! Quick Question: the pieces behind the `qq` binary, usable on their own.
+//!
+//! [`providers::Provider`] is the interface to the language model backends and
+//! [`render`] turns their markdown answers into terminal output.
+
+pub mod batch;
+pub mod budget;
+pub mod cache;
+pub mod cancel;
+pub mod clipboard;
+pub mod compare;
+pub mod completions;
+pub mod config;
+pub mod document;
+pub mod image;
+pub mod input;
+pub mod links;
+pub mod mangling;
+pub mod prompt;
+pub mod providers;
+pub mod render;
+pub mod serve;
+pub mod shell;
+pub mod template;
+pub mod why;
@@ -0,0 +1,198 @@
+//! Markdown links in answers: terminal hyperlinks, the numbered list under
+//! the answer, and opening one later with `qq --open N`.
+
+use anyhow::Result;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::config::Config;
+
+/// A `[text](url)` in an answer, as byte offsets into it.
+struct Link<'a> {
+    start: usize,
+    end: usize,
+    text: &'a str,
+    url: &'a str,
+}
+
+fn parse(text: &str) -> Vec<Link<'_>> {
+    let mut links = Vec::new();
+    let mut from = 0;
+    while let Some(open) = text[from..].find('[').map(|i| from + i) {
+        from = open + 1;
+        let Some(close) = text[open..].find("](").map(|i| open + i) else { break };
+        let label = &text[open + 1..close];
+        if label.contains(['[', '\n']) {
+            continue;
+        }
+
+        // URLs like .../Rust_(programming_language) have parentheses of their own
+        let url_start = close + 2;
+        let mut depth = 0;
+        let mut url_end = None;
+        for (i, c) in text[url_start..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => {
+                    url_end = Some(url_start + i);
+                    break;
+                }
+                ')' => depth -= 1,
+                c if c.is_whitespace() => break,
+                _ => {}
+            }
+        }
+        let Some(url_end) = url_end else { continue };
+        let url = &text[url_start..url_end];
+        if label.is_empty() || url.is_empty() {
+            continue;
+        }
+
+        links.push(Link { start: open, end: url_end + 1, text: label, url });
+        from = url_end + 1;
+    }
+    links
+}
+
+/// URLs of the links in `text`, in order of first appearance.
+pub fn find(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for link in parse(text) {
+        if !urls.iter().any(|url| url == link.url) {
+            urls.push(link.url.to_string());
+        }
+    }
+    urls
+}
+
+/// Rewrites each link as a clickable hyperlink followed by its number, or as
+/// `text <url> [n]` when the terminal can't do hyperlinks. New URLs are added to
+/// `numbered`, so a continuation keeps counting from the first part.
+pub fn render(text: &str, numbered: &mut Vec<String>, hyperlinks: bool) -> String {
+    let mut rendered = String::new();
+    let mut last = 0;
+    for link in parse(text) {
+        let number = match numbered.iter().position(|url| url == link.url) {
+            Some(index) => index + 1,
+            None => {
+                numbered.push(link.url.to_string());
+                numbered.len()
+            }
+        };
+
+        rendered.push_str(&text[last..link.start]);
+        if hyperlinks {
+            rendered.push_str(&hyperlink(link.url, link.text));
+            rendered.push_str(&format!("\x1b[2m[{}]\x1b[0m", number));
+        } else {
+            rendered.push_str(&format!("{} <{}> [{}]", link.text, link.url, number));
+        }
+        last = link.end;
+    }
+    rendered.push_str(&text[last..]);
+    rendered
+}
+
+/// Rewrites each link as `text <url>`, for copies of an answer that come
+/// without the numbered list.
+pub fn inline(text: &str) -> String {
+    let mut rendered = String::new();
+    let mut last = 0;
+    for link in parse(text) {
+        rendered.push_str(&text[last..link.start]);
+        rendered.push_str(&format!("{} <{}>", link.text, link.url));
+        last = link.end;
+    }
+    rendered.push_str(&text[last..]);
+    rendered
+}
+
+/// `text` as an OSC 8 hyperlink to `url`.
+pub fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Whether stdout is a terminal known to understand OSC 8 hyperlinks.
+/// `FORCE_HYPERLINK=1` or `0` overrides the guess.
+pub fn supported() -> bool {
+    if let Ok(force) = std::env::var("FORCE_HYPERLINK") {
+        return force != "0";
+    }
+    if !io::stdout().is_terminal() {
+        return false;
+    }
+
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    let term_program = var("TERM_PROGRAM");
+    let term = var("TERM");
+    ["WT_SESSION", "KITTY_WINDOW_ID", "KONSOLE_VERSION", "DOMTERM"]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some())
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|version| version >= 5000)
+        || ["iTerm.app", "WezTerm", "vscode", "Hyper", "ghostty", "Tabby"].contains(&term_program.as_str())
+        || ["xterm-kitty", "alacritty", "foot", "wezterm", "xterm-ghostty"]
+            .iter()
+            .any(|name| term.starts_with(name))
+}
+
+/// Where the links of the last answer are kept for `qq --open`.
+pub fn path() -> Result<PathBuf> {
+    let mut path = Config::cache_dir()?;
+    path.push("last_links");
+    Ok(path)
+}
+
+/// Remembers the links of the answer just shown, one URL per line.
+pub fn save(urls: &[String]) -> Result<()> {
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, urls.join("\n"))?;
+    Ok(())
+}
+
+/// The links of the last answer.
+pub fn load() -> Vec<String> {
+    path()
+        .and_then(|path| Ok(fs::read_to_string(path)?))
+        .map(|content| content.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Opens `url` in `$BROWSER`, or whatever the platform opens links with.
+/// Only web links are opened: the URL comes from the model, and anything
+/// else (say `-x` or `file:`) could be read by the opener as an option or
+/// point somewhere local.
+pub fn open(url: &str) -> Result<()> {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Err(anyhow::anyhow!("Not opening {}: only http and https links can be opened", url));
+    }
+
+    let mut command = match std::env::var("BROWSER").ok().filter(|browser| !browser.trim().is_empty()) {
+        Some(browser) => {
+            let mut parts = browser.split_whitespace();
+            let mut command = Command::new(parts.next().unwrap_or_default());
+            command.args(parts);
+            command
+        }
+        None if cfg!(target_os = "macos") => Command::new("open"),
+        None if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        None => Command::new("xdg-open"),
+    };
+
+    let status = command
+        .arg(url)
+        .status()
+        .map_err(|e| anyhow::anyhow!("Could not open {}: {}", url, e))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("Opening {} failed ({})", url, status));
+    }
+    Ok(())
+}
@@ -1,13 +1,29 @@
-use clap::Parser;
-use std::io::{self, Write, IsTerminal, Read};
+use clap::{CommandFactory, Parser, Subcommand};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::io::{self, IsTerminal, Read, Write};
 use std::time::Duration;
 use indicatif::{ProgressBar, ProgressStyle};
 
-mod config;
-mod providers;
-
-use config::Config;
-use providers::{Provider, claude::ClaudeProvider};
+use quick_question::batch::{self, BatchFormat, BatchOptions};
+use quick_question::budget::{self, Ledger};
+use quick_question::cache::{self, Cache};
+use quick_question::cancel;
+use quick_question::clipboard;
+use quick_question::compare;
+use quick_question::completions;
+use quick_question::config::Config;
+use quick_question::document::Document;
+use quick_question::image::Image;
+use quick_question::input;
+use quick_question::links;
+use quick_question::prompt::{self, Prompt};
+use quick_question::providers::{self, Answer, ProviderError, Target, Usage, claude::MIN_THINKING_BUDGET};
+use quick_question::render::{self, Typing};
+use quick_question::serve;
+use quick_question::shell::{self, Shell};
+use quick_question::template::Template;
+use quick_question::why::{self, LastFailure};
 
 #[derive(Parser)]
 #[command(name = "qq")]
@@ -17,73 +33,575 @@ use providers::{Provider, claude::ClaudeProvider};
 Usage examples:
   qq \"What is Rust?\"        # Ask a question directly
   qq                         # Enter interactive mode
-  echo \"question\" | qq      # Pipe input")]
+  qq -e                      # Write a longer question in your editor
+  echo \"question\" | qq      # Pipe input
+  eval \"$(qq init bash)\"    # Ctrl-G and qq! in your shell
+  qq why                     # Explain the last failed command
+  qq --open 1                # Open the first link of the last answer
+  qq --copy-code \"tar it\"   # Copy the command from the answer
+  git diff | qq -t commit    # Fill the 'commit' prompt template
+  qq --as tutor \"monads?\"    # Answer as a persona
+  qq -i shot.png \"why?\"      # Ask about an image
+  qq --compare a,b \"why?\"    # Ask two models side by side
+  qq batch questions.txt     # Answer a file of questions
+  qq serve --port 7878       # Answer over a local HTTP API
+  qq completions zsh         # Tab completion script")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The question to ask (if not provided, enters interactive mode)
     question: Vec<String>,
     
     /// Run the setup process
     #[arg(long)]
     setup: bool,
+
+    /// Open the Nth link from the last answer in your browser
+    #[arg(long, value_name = "N", conflicts_with = "question")]
+    open: Option<usize>,
+
+    /// Don't read from or write to the answer cache
+    #[arg(global = true, long)]
+    no_cache: bool,
+
+    /// Ask again even if a cached answer exists, and cache the new one
+    #[arg(global = true, long, conflicts_with = "no_cache")]
+    refresh: bool,
+
+    /// Show token usage (including prompt cache reads/writes) and cost
+    #[arg(global = true, short, long)]
+    verbose: bool,
+
+    /// Print the answer and usage as JSON instead of formatted text
+    #[arg(global = true, long)]
+    json: bool,
+
+    /// Let Claude think before answering, optionally with a token budget (--think=8000)
+    #[arg(global = true, long, value_name = "BUDGET", num_args = 0..=1, require_equals = true, default_missing_value = "4000")]
+    think: Option<u32>,
+
+    /// Show the model's reasoning (dimmed) before the answer
+    #[arg(global = true, long)]
+    show_thinking: bool,
+
+    /// Automatically continue answers that hit the max_tokens limit
+    #[arg(global = true, long)]
+    more: bool,
+
+    /// Copy the answer to the clipboard, without formatting
+    #[arg(global = true, long)]
+    copy: bool,
+
+    /// Copy the answer's code blocks, or just the Nth one (--copy-code=2)
+    #[arg(global = true, long, value_name = "N", num_args = 0..=1, require_equals = true, conflicts_with = "copy")]
+    copy_code: Option<Option<usize>>,
+
+    /// Ask several providers or models at once and compare the answers side by side
+    #[arg(long, value_name = "[PROVIDER:]MODEL,...", value_delimiter = ',', num_args = 1, conflicts_with_all = ["cmd", "copy", "copy_code", "more"])]
+    compare: Vec<String>,
+
+    /// Reply with a single shell command and nothing else (used by `qq init`)
+    #[arg(long, conflicts_with = "json")]
+    cmd: bool,
+
+    /// Write the question in $VISUAL or $EDITOR, starting from any question or piped text
+    #[arg(short, long, conflicts_with = "cmd")]
+    editor: bool,
+
+    /// Attach a PDF, Word, HTML or text file, optionally just some pages (-f spec.pdf:3-7); repeatable
+    #[arg(short, long = "file", value_name = "PATH[:PAGES]", conflicts_with = "cmd")]
+    files: Vec<String>,
+
+    /// Attach an image (PNG, JPEG, GIF or WebP) to the question; repeatable
+    #[arg(short, long = "image", value_name = "PATH", conflicts_with = "cmd")]
+    images: Vec<PathBuf>,
+
+    /// Ask with a saved prompt template (see `qq templates list`)
+    #[arg(short, long, value_name = "NAME", conflicts_with = "cmd")]
+    template: Option<String>,
+
+    /// Answer as a persona: terse, tutor, reviewer or one from your config
+    #[arg(long = "as", value_name = "PERSONA", conflicts_with_all = ["template", "cmd"])]
+    persona: Option<String>,
+
+    /// Fill a template placeholder, e.g. --var lang=de (repeatable)
+    #[arg(long = "var", value_name = "KEY=VALUE", value_parser = parse_var, requires = "template")]
+    vars: Vec<(String, String)>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print shell integration (Ctrl-G command suggestions and qq!) for your rc file
+    Init {
+        /// Shell to generate the snippet for (defaults to $SHELL)
+        shell: Option<Shell>,
+    },
+    /// Explain why the last command failed (needs the `qq init` shell hook)
+    Why {
+        /// Anything after `why` is asked as a normal question instead
+        question: Vec<String>,
+    },
+    /// Print a tab completion script for your shell
+    Completions {
+        shell: clap_complete::Shell,
+    },
+    /// List, show or create prompt templates
+    Templates {
+        #[command(subcommand)]
+        action: TemplatesCommand,
+    },
+    /// Answer every question in a file, a few at a time
+    Batch {
+        /// One question per line, or JSON lines like {"question": "...", "model": "..."}
+        file: PathBuf,
+        /// Where to write the answers (defaults to FILE.answers.jsonl)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// How many questions to ask at once
+        #[arg(short, long, default_value_t = 4)]
+        jobs: usize,
+        /// Output format (defaults to markdown for .md outputs, jsonl otherwise)
+        #[arg(long, value_enum)]
+        format: Option<BatchFormat>,
+    },
+    /// Answer questions over a local HTTP API
+    Serve {
+        /// Port to listen on (0 picks a free one)
+        #[arg(short, long, default_value_t = 7878)]
+        port: u16,
+        /// Address to bind; anything but localhost needs a --token
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Require `Authorization: Bearer TOKEN` (also read from QQ_SERVE_TOKEN)
+        #[arg(long)]
+        token: Option<String>,
+    },
 }
 
+#[derive(Subcommand)]
+enum TemplatesCommand {
+    /// List your saved templates
+    List,
+    /// Print a template
+    Show { name: String },
+    /// Create a template from a starter file
+    New { name: String },
+}
+
+// How many times --more (or the prompt) will extend a single answer
+const MAX_CONTINUATIONS: usize = 5;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // The completion scripts ask for template names and such at tab time
+    if let Ok(kind) = std::env::var("QQ_COMPLETE") {
+        for candidate in completions::candidates(&kind) {
+            println!("{}", candidate);
+        }
+        return Ok(());
+    }
+
     let args = Args::parse();
     
     if args.setup {
         setup_config().await?;
         return Ok(());
     }
-    
-    let question = if args.question.is_empty() {
-        // Check if we have stdin input (piped)
-        if !IsTerminal::is_terminal(&io::stdin()) {
-            // Read from stdin (pipe or redirection)
-            let mut input = String::new();
-            io::stdin().read_to_string(&mut input)?;
-            input.trim().to_string()
-        } else {
-            // No arguments and no pipe, go interactive
-            get_question_interactively()?
+
+    if let Some(number) = args.open {
+        return open_link(number);
+    }
+
+    if let Some(Command::Init { shell }) = &args.command {
+        let Some(shell) = shell.or_else(Shell::detect) else {
+            eprintln!("Couldn't tell which shell you use. Try: qq init bash|zsh|fish");
+            std::process::exit(1);
+        };
+        print!("{}", shell.init_script(&Config::cache_dir()?));
+        return Ok(());
+    }
+
+    if let Some(Command::Completions { shell }) = &args.command {
+        print!("{}", completions::script(*shell, &mut Args::command()));
+        return Ok(());
+    }
+
+    if let Some(Command::Templates { action }) = &args.command {
+        return run_templates(action);
+    }
+
+    if let Some(Command::Batch { file, output, jobs, format }) = &args.command {
+        return run_batch(file, output.as_deref(), *jobs, *format).await;
+    }
+
+    if let Some(Command::Serve { port, host, token }) = &args.command {
+        return run_serve(host, *port, token.clone()).await;
+    }
+
+    let template = args.template.as_deref().map(|name| {
+        Template::load(name).unwrap_or_else(|e| {
+            eprintln!("❌ {}", e);
+            eprintln!("💡 See your templates with: qq templates list");
+            std::process::exit(1);
+        })
+    });
+
+    let mut prompt = match &args.command {
+        Some(Command::Why { question }) if question.is_empty() => why_prompt()?,
+        Some(Command::Why { question }) => {
+            // `qq why is the sky blue` is just a question
+            let words: Vec<String> = std::iter::once("why".to_string()).chain(question.iter().cloned()).collect();
+            question_prompt(&args, &words)?
+        }
+        _ => match &template {
+            Some(template) => template_prompt(&args, template)?,
+            None => question_prompt(&args, &args.question)?,
+        },
+    };
+    let question = prompt.question.clone();
+
+    let mut config = match Config::load() {
+        Ok(config) => config,
+        Err(_) => {
+            println!("🔧 First time setup needed!");
+            setup_config().await?;
+            Config::load()?
+        }
+    };
+    if let Some(template) = &template {
+        template.apply(&mut config);
+    }
+    if let Some(name) = &args.persona {
+        let Some(persona) = prompt::find_persona(&config, name) else {
+            eprintln!("❌ No persona named '{}'", name);
+            eprintln!("💡 Available: {}", prompt::persona_names(&config).join(", "));
+            std::process::exit(1);
+        };
+        prompt.system = prompt::persona_system_prompt(&persona);
+        persona.apply(&mut config);
+    }
+
+    for path in &args.images {
+        match Image::load(path, config.image_max_edge) {
+            Ok(image) => prompt.images.push(image),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            }
         }
+    }
+
+    // From here on Ctrl-C skips typing or cancels, instead of killing qq mid-style
+    cancel::install();
+
+    let thinking_budget = args
+        .think
+        .or(config.thinking_budget)
+        .filter(|budget| *budget > 0)
+        .map(|budget| budget.max(MIN_THINKING_BUDGET));
+    if !args.compare.is_empty() {
+        return run_compare(&args, &config, prompt, thinking_budget).await;
+    }
+    let provider = providers::from_config(&config, thinking_budget)?;
+
+    for spec in &args.files {
+        match Document::load(spec, provider.reads_pdfs()) {
+            Ok(document) => prompt.documents.push(document),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let system_prompt = prompt.system_text();
+    // Anything that isn't a normal answer on a terminal skips the spinner and prompts
+    let plain = args.json || args.cmd;
+
+    // A cache that fails to open just means we always ask the API
+    let cache = if args.no_cache || !config.cache.enabled {
+        None
     } else {
-        // Check if we might have gotten mangled input from shell
-        let joined = args.question.join(" ");
-        if looks_like_incomplete_input(&joined) {
-            println!("🤔 It looks like your question might have been cut off by the shell.");
-            println!("💡 Tip: Put quotes around questions with apostrophes or special characters:");
-            println!("   qq \"your question here\"");
-            println!("   Or just use: qq (and enter your question when prompted)");
-            println!();
-            print!("Enter your complete question: ");
-            io::stdout().flush()?;
-            let mut question = String::new();
-            io::stdin().read_line(&mut question)?;
-            question.trim().to_string()
-        } else {
-            joined
+        Cache::open(&config.cache).ok()
+    };
+    let mut params = format!("max_tokens={};thinking={:?}", config.max_tokens, thinking_budget);
+    if !prompt.documents.is_empty() {
+        let digests: Vec<String> = prompt.documents.iter().map(Document::digest).collect();
+        params.push_str(&format!(";documents={}", digests.join(",")));
+    }
+    if !prompt.images.is_empty() {
+        let digests: Vec<String> = prompt.images.iter().map(Image::digest).collect();
+        params.push_str(&format!(";images={}", digests.join(",")));
+    }
+    let cache_key = Cache::key(&config.provider, &config.model, &params, &system_prompt, &question);
+
+    if let Some(cache) = &cache {
+        if !args.refresh {
+            if let Some(entry) = cache.get(&cache_key) {
+                let answer = Answer {
+                    text: entry.text,
+                    thinking: entry.thinking,
+                    stop_reason: entry.stop_reason,
+                    usage: Usage::default(),
+                };
+                let _ = links::save(&links::find(&answer.text));
+                copy_answer(&args, &answer.text);
+                if args.json {
+                    render::print_json(&question, &config.model, &answer, true)?;
+                    return Ok(());
+                }
+                if args.cmd {
+                    println!("{}", shell::extract_command(&answer.text));
+                    return Ok(());
+                }
+                render::print_answer(&answer, args.show_thinking, Typing::from_config(&config));
+                println!(
+                    "\x1b[2m  ⚡ cached answer from {} (use --refresh to ask again)\x1b[0m",
+                    cache::describe_age(entry.created_at)
+                );
+                if answer.is_truncated() {
+                    eprintln!("\x1b[33m  ✂️  This answer was truncated. Use --refresh --more to get the full answer.\x1b[0m");
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    // Check spending and rate limits before anything goes over the wire
+    let mut ledger = Ledger::load()?;
+    let max_output_tokens = config.max_tokens + thinking_budget.unwrap_or(0);
+    let estimate = budget::estimate_cost(&config.model, prompt_chars(&prompt), max_output_tokens);
+    check_budget(&config, &ledger, estimate)?;
+
+    let spinner = new_spinner(plain);
+    let mut answer = match cancel::abortable(provider.ask(&prompt)).await {
+        Ok(answer) => answer,
+        Err(e) => {
+            spinner.finish_and_clear();
+            exit_with_error(&e);
+        }
+    };
+    spinner.finish_and_clear();
+
+    // Whoever answered is who gets billed and credited
+    let fell_back_to = provider.fell_back_to();
+    let model = fell_back_to.as_ref().map_or(config.model.clone(), |target| target.model.clone());
+    if let Some(target) = &fell_back_to {
+        eprintln!(
+            "\x1b[33m  ↪️  {} is unavailable, answered by {}\x1b[0m",
+            Target::of(&config).label(),
+            target.label()
+        );
+    }
+
+    if let Err(e) = ledger.record(&model, &answer.usage) {
+        eprintln!("⚠️  Could not record usage: {}", e);
+    }
+    if !plain {
+        render::print_answer(&answer, args.show_thinking, Typing::from_config(&config));
+    }
+
+    // Keep going while the answer is cut off and the user wants the rest
+    let mut continuations = 0;
+    while answer.is_truncated() && continuations < MAX_CONTINUATIONS {
+        if !plain {
+            render::print_truncation_notice(config.max_tokens, args.more);
+        }
+        if !args.more && (plain || !input::confirm("Continue the answer?")?) {
+            break;
         }
+
+        let estimate = budget::estimate_cost(&config.model, prompt_chars(&prompt) + answer.text.len(), config.max_tokens);
+        check_budget(&config, &ledger, estimate)?;
+
+        let spinner = new_spinner(plain);
+        let more = match cancel::abortable(provider.continue_answer(&prompt, &answer.text)).await {
+            Ok(more) => more,
+            Err(e) => {
+                spinner.finish_and_clear();
+                exit_with_error(&e);
+            }
+        };
+        spinner.finish_and_clear();
+
+        let model = provider.fell_back_to().map_or(config.model.clone(), |target| target.model);
+        if let Err(e) = ledger.record(&model, &more.usage) {
+            eprintln!("⚠️  Could not record usage: {}", e);
+        }
+        if !plain {
+            render::print_continuation(&answer.text, &more.text, Typing::from_config(&config));
+        }
+
+        // The continuation picks up after the trimmed partial answer
+        answer.text.truncate(answer.text.trim_end().len());
+        answer.text.push_str(&more.text);
+        answer.stop_reason = more.stop_reason;
+        answer.usage.add(&more.usage);
+        continuations += 1;
+    }
+
+    if let Some(cache) = &cache {
+        let _ = cache.put_answer(&cache_key, &question, provider.as_ref(), &answer);
+    }
+    if !args.cmd {
+        let _ = links::save(&links::find(&answer.text));
+    }
+    copy_answer(&args, &answer.text);
+    if args.json {
+        render::print_json(&question, &model, &answer, false)?;
+    } else if args.cmd {
+        println!("{}", shell::extract_command(&answer.text));
+    } else if args.verbose {
+        render::print_usage(&model, &answer.usage);
+    }
+    
+    Ok(())
+}
+
+/// Builds the prompt for a regular question (or a `--cmd` request), exiting
+/// if there's no question to ask.
+fn question_prompt(args: &Args, words: &[String]) -> anyhow::Result<Prompt> {
+    let question = if args.editor {
+        compose_in_editor(words)?
+    } else {
+        // The shell integration passes the buffer quoted, so don't second-guess it
+        let check_mangling = !args.cmd && Config::load().map_or(true, |config| config.check_mangling);
+        input::read_question(words, check_mangling)?
     };
     
     if question.trim().is_empty() {
+        if args.cmd {
+            std::process::exit(1);
+        }
         println!("❌ No question provided.");
-        return Ok(());
+        std::process::exit(0);
     }
 
-    let config = match Config::load() {
-        Ok(config) => config,
-        Err(_) => {
-            println!("🔧 First time setup needed!");
-            setup_config().await?;
-            Config::load()?
+    if args.cmd {
+        Ok(Prompt::with_system(vec![shell::command_prompt(Shell::detect())], &question))
+    } else {
+        Prompt::new(&question)
+    }
+}
+
+/// Opens the editor pre-filled with the question words and any piped text.
+fn compose_in_editor(words: &[String]) -> anyhow::Result<String> {
+    let mut initial = words.join(" ");
+    if !io::stdin().is_terminal() {
+        let mut piped = String::new();
+        io::stdin().read_to_string(&mut piped)?;
+        if !piped.trim().is_empty() {
+            if !initial.is_empty() {
+                initial.push_str("\n\n");
+            }
+            initial.push_str(piped.trim());
         }
+    }
+    input::read_from_editor(&initial)
+}
+
+/// Fills a template with the question words or piped input and `--var`s.
+fn template_prompt(args: &Args, template: &Template) -> anyhow::Result<Prompt> {
+    let input = if args.editor {
+        compose_in_editor(&args.question)?
+    } else if !args.question.is_empty() {
+        args.question.join(" ")
+    } else if !io::stdin().is_terminal() {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        input
+    } else if template.uses_input() {
+        input::get_question_interactively()?
+    } else {
+        String::new()
     };
 
-    let provider = ClaudeProvider::new(config.claude_api_key.clone(), config.model.clone());
+    if template.uses_input() && input.trim().is_empty() {
+        println!("❌ No input for template '{}'.", template.name);
+        std::process::exit(0);
+    }
+
+    let vars: HashMap<String, String> = args.vars.iter().cloned().collect();
+    template.to_prompt(&input, &vars).map_err(|e| {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn parse_var(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", value)),
+    }
+}
+
+fn run_templates(action: &TemplatesCommand) -> anyhow::Result<()> {
+    match action {
+        TemplatesCommand::List => {
+            let names = Template::names();
+            if names.is_empty() {
+                println!("No templates yet. Create one with: qq templates new <name>");
+                println!("📁 Templates live in {}", Config::templates_dir()?.display());
+            }
+            for name in names {
+                match Template::load(&name) {
+                    Ok(template) => println!(
+                        "{:<20} \x1b[2m{}\x1b[0m",
+                        name,
+                        template.description.unwrap_or_default()
+                    ),
+                    Err(e) => println!("{:<20} \x1b[31m{}\x1b[0m", name, e),
+                }
+            }
+        }
+        TemplatesCommand::Show { name } => {
+            let path = Template::path(name)?;
+            match std::fs::read_to_string(&path) {
+                Ok(content) => print!("{}", content),
+                Err(_) => {
+                    eprintln!("❌ No template named '{}'", name);
+                    std::process::exit(1);
+                }
+            }
+        }
+        TemplatesCommand::New { name } => {
+            let path = Template::create(name).unwrap_or_else(|e| {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            });
+            println!("✅ Created {}", path.display());
+            println!("💡 Edit it, then run: qq -t {} \"your input\"", name);
+        }
+    }
+    Ok(())
+}
+
+/// Builds the `qq why` diagnosis prompt from the last recorded failure.
+fn why_prompt() -> anyhow::Result<Prompt> {
+    let shell = Shell::detect().map(|shell| shell.name());
+
+    // `make 2>&1 | qq why` supplies the error output directly
+    if !io::stdin().is_terminal() {
+        let mut output = String::new();
+        io::stdin().read_to_string(&mut output)?;
+        if !output.trim().is_empty() {
+            return Ok(why::output_prompt(&output, shell));
+        }
+    }
+
+    let Some(failure) = LastFailure::load()? else {
+        eprintln!("🤷 No failed command recorded yet.");
+        eprintln!("💡 qq why needs the shell hook: add eval \"$(qq init bash)\" (or zsh/fish) to your shell config.");
+        std::process::exit(1);
+    };
+    Ok(failure.to_prompt(shell))
+}
 
+fn new_spinner(hidden: bool) -> ProgressBar {
     // Show spinner while waiting for response
-    let spinner = ProgressBar::new_spinner();
+    let spinner = if hidden { ProgressBar::hidden() } else { ProgressBar::new_spinner() };
     spinner.set_style(
         ProgressStyle::default_spinner()
             .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈")
@@ -92,32 +610,243 @@ async fn main() -> anyhow::Result<()> {
     );
     
     // Simple rotating messages
-    let messages = vec!["Thinking...", "Processing...", "Analyzing...", "Computing..."];
+    let messages = ["Thinking...", "Processing...", "Analyzing...", "Computing..."];
     spinner.set_message(messages[0]);
     spinner.enable_steady_tick(Duration::from_millis(120));
+    spinner
+}
 
-    match provider.ask(&question).await {
-        Ok(response) => {
-            spinner.finish_and_clear();
-            print!("\n\x1b[36m  › \x1b[0m"); // Cyan chevron with indent
-            io::stdout().flush().unwrap();
-            print_with_typing_effect(&response);
-        },
-        Err(e) => {
-            spinner.finish_and_clear();
-            if e.to_string().contains("network") || e.to_string().contains("connection") {
-                eprintln!("Sorry, I can't answer that without an active internet connection");
-            } else if e.to_string().contains("token") || e.to_string().contains("quota") {
-                eprintln!("Looks like you ran out of tokens, time to pay up.");
-            } else if e.to_string().contains("401") || e.to_string().contains("authentication") {
-                eprintln!("Authentication failed. Check your API key configuration.");
-            } else {
-                eprintln!("Something went wrong: {}", e);
+/// Puts the answer, or the code in it, on the clipboard for --copy and
+/// --copy-code. The answer is already out, so failing only warns.
+fn copy_answer(args: &Args, answer: &str) {
+    let (what, text) = if args.copy {
+        ("answer".to_string(), render::plain_text(answer))
+    } else if let Some(number) = args.copy_code {
+        let blocks = clipboard::code_blocks(answer);
+        match number {
+            _ if blocks.is_empty() => {
+                eprintln!("⚠️  The answer has no code to copy.");
+                return;
+            }
+            None => ("code".to_string(), blocks.join("\n\n")),
+            Some(number) => match number.checked_sub(1).and_then(|index| blocks.get(index)) {
+                Some(block) => (format!("code block {}", number), block.clone()),
+                None => {
+                    eprintln!("⚠️  The answer only has {} code block(s).", blocks.len());
+                    return;
+                }
+            },
+        }
+    } else {
+        return;
+    };
+
+    match clipboard::copy(&text) {
+        // The terminal may not support it, so don't promise it worked
+        Ok("terminal") => eprintln!("📋 Sent the {} to the terminal clipboard (OSC 52)", what),
+        Ok(_) => eprintln!("📋 Copied the {} to the clipboard", what),
+        Err(e) => eprintln!("⚠️  Could not copy the {}: {}", what, e),
+    }
+}
+
+/// Opens a link from the numbered list under the last answer.
+fn open_link(number: usize) -> anyhow::Result<()> {
+    let urls = links::load();
+    let Some(url) = number.checked_sub(1).and_then(|index| urls.get(index)) else {
+        match urls.len() {
+            0 => eprintln!("❌ The last answer had no links."),
+            1 => eprintln!("❌ The last answer only had 1 link."),
+            count => eprintln!("❌ The last answer only had {} links.", count),
+        }
+        std::process::exit(1);
+    };
+
+    println!("🔗 Opening {}", url);
+    if let Err(e) = links::open(url) {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run_batch(file: &Path, output: Option<&Path>, jobs: usize, format: Option<BatchFormat>) -> anyhow::Result<()> {
+    let content = std::fs::read_to_string(file).unwrap_or_else(|e| {
+        eprintln!("❌ Could not read {}: {}", file.display(), e);
+        std::process::exit(1);
+    });
+    let items = batch::parse_items(&content).unwrap_or_else(|e| {
+        eprintln!("❌ {} {}", file.display(), e);
+        std::process::exit(1);
+    });
+    if items.is_empty() {
+        eprintln!("❌ No questions in {}", file.display());
+        std::process::exit(1);
+    }
+
+    let output = output.map_or_else(|| file.with_extension("answers.jsonl"), Path::to_path_buf);
+    let options = BatchOptions {
+        format: format.unwrap_or_else(|| BatchFormat::for_path(&output)),
+        output,
+        jobs,
+    };
+    // The output is started over as answers arrive, which would lose the questions
+    let same_file = |path: &Path| {
+        matches!((file.canonicalize(), path.canonicalize()), (Ok(input), Ok(path)) if input == path)
+    };
+    if same_file(&options.output) || same_file(&options.progress_path()) {
+        eprintln!("❌ {} is the file of questions", options.output.display());
+        eprintln!("💡 Pick another output with -o");
+        std::process::exit(1);
+    }
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("❌ Could not load your config: {}", e);
+        eprintln!("💡 Set qq up first with: qq --setup");
+        std::process::exit(1);
+    });
+
+    cancel::install();
+    let summary = batch::run(&config, &items, &options).await?;
+
+    if summary.resumed > 0 {
+        println!("⏩ {} answered by an earlier run", summary.resumed);
+    }
+    println!("✅ {} answered → {}", summary.answered, options.output.display());
+    if let Some(reason) = &summary.stopped {
+        eprintln!("💸 Stopped early: {}", reason);
+    }
+    if summary.failed > 0 {
+        eprintln!("❌ {} failed", summary.failed);
+    }
+    if summary.failed > 0 || summary.stopped.is_some() {
+        eprintln!("💡 Run the same command again to finish the rest");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run_serve(host: &str, port: u16, token: Option<String>) -> anyhow::Result<()> {
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("❌ Could not load your config: {}", e);
+        eprintln!("💡 Set qq up first with: qq --setup");
+        std::process::exit(1);
+    });
+    let token = token
+        .or_else(|| std::env::var("QQ_SERVE_TOKEN").ok())
+        .filter(|token| !token.is_empty());
+
+    let listener = tokio::net::TcpListener::bind((host, port)).await.unwrap_or_else(|e| {
+        eprintln!("❌ Could not listen on {}:{}: {}", host, port, e);
+        std::process::exit(1);
+    });
+    let address = listener.local_addr()?;
+    // Without a token only local requests get in, so nobody else could use it
+    if !address.ip().is_loopback() && token.is_none() {
+        eprintln!("❌ Serving on {} would let anyone who can reach it spend your API credit", address);
+        eprintln!("💡 Add --token, or leave --host at 127.0.0.1");
+        std::process::exit(1);
+    }
+
+    println!("🌐 Serving {} on http://{}", Target::of(&config).label(), address);
+    println!("💡 Try: curl -s http://{}/ask -H 'Content-Type: application/json' -d '{{\"question\": \"hi\"}}'", address);
+    serve::Server::new(config, token)?.run(listener).await
+}
+
+/// Puts the question to every `--compare` target at once, shows how each
+/// did and keeps a record of it.
+async fn run_compare(args: &Args, config: &Config, mut prompt: Prompt, thinking_budget: Option<u32>) -> anyhow::Result<()> {
+    let mut targets = Vec::new();
+    for spec in &args.compare {
+        let target = Target::parse(spec, config).unwrap_or_else(|e| {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        });
+        let provider = providers::from_config(&target.config(config), thinking_budget)?;
+        targets.push((target, provider));
+    }
+
+    // PDFs go as they are only if every provider can read them
+    let native_pdf = targets.iter().all(|(_, provider)| provider.reads_pdfs());
+    for spec in &args.files {
+        match Document::load(spec, native_pdf) {
+            Ok(document) => prompt.documents.push(document),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let mut ledger = Ledger::load()?;
+    let prompt_chars = prompt_chars(&prompt);
+    let max_output_tokens = config.max_tokens + thinking_budget.unwrap_or(0);
+    let estimate: f64 = targets
+        .iter()
+        .map(|(target, _)| budget::estimate_cost(&target.model, prompt_chars, max_output_tokens))
+        .sum();
+    check_budget(config, &ledger, estimate)?;
+
+    let question = prompt.question.clone();
+    let spinner = new_spinner(args.json);
+    let outcomes = cancel::abortable(compare::run(prompt, targets)).await;
+    spinner.finish_and_clear();
+
+    for outcome in &outcomes {
+        if let Ok(answer) = &outcome.answer {
+            if let Err(e) = ledger.record(&outcome.target.model, &answer.usage) {
+                eprintln!("⚠️  Could not record usage: {}", e);
             }
+        }
+    }
+
+    let created_at = budget::now();
+    let record = compare::record(&question, &outcomes, created_at);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&record)?);
+    } else {
+        compare::print(&outcomes);
+    }
+    match compare::save_record(&record, created_at) {
+        Ok(path) => eprintln!("\x1b[2m  📝 Comparison saved to {}\x1b[0m", path.display()),
+        Err(e) => eprintln!("⚠️  Could not save the comparison: {}", e),
+    }
+
+    if outcomes.iter().all(|outcome| outcome.answer.is_err()) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn exit_with_error(e: &anyhow::Error) -> ! {
+    match ProviderError::of(e) {
+        Some(ProviderError::Auth(_)) => eprintln!("Authentication failed. Check your API key configuration."),
+        Some(ProviderError::RateLimited(_)) => eprintln!("Slow down! The API is rate limiting you, try again in a moment."),
+        Some(ProviderError::Overloaded(_)) => eprintln!("The API is overloaded right now, try again in a bit."),
+        Some(ProviderError::Network(_)) => eprintln!("Sorry, I can't answer that without an active internet connection"),
+        _ if e.to_string().contains("token") || e.to_string().contains("quota") => {
+            eprintln!("Looks like you ran out of tokens, time to pay up.")
+        }
+        _ => eprintln!("Something went wrong: {}", e),
+    }
+    std::process::exit(1);
+}
+
+/// Roughly how many characters `prompt` sends, attachments included, since
+/// budget estimates count characters at about 4 per token.
+fn prompt_chars(prompt: &Prompt) -> usize {
+    let attachment_tokens: u32 = prompt.documents.iter().map(Document::estimated_tokens).sum::<u32>()
+        + prompt.images.iter().map(Image::estimated_tokens).sum::<u32>();
+    prompt.system_text().len() + prompt.question.len() + attachment_tokens as usize * 4
+}
+
+/// Checks a request estimated to cost `estimate` in all against the budget,
+/// asking whether to go over.
+fn check_budget(config: &Config, ledger: &Ledger, estimate: f64) -> anyhow::Result<()> {
+    if let Some(reason) = budget::check(&config.budget, ledger, estimate, budget::now()) {
+        eprintln!("💸 {}", reason);
+        if !input::confirm_over_budget()? {
             std::process::exit(1);
         }
     }
-    
     Ok(())
 }
 
@@ -139,8 +868,7 @@ async fn setup_config() -> anyhow::Result<()> {
     
     let config = Config {
         claude_api_key: api_key,
-        model: "claude-sonnet-4-20250514".to_string(),
-        max_tokens: 300,
+        ..Config::default()
     };
     
     config.save()?;
@@ -158,54 +886,3 @@ async fn setup_config() -> anyhow::Result<()> {
     
     Ok(())
 }
-
-fn get_question_interactively() -> anyhow::Result<String> {
-    // Interactive terminal input only
-    print!("❓ Enter your question: ");
-    io::stdout().flush()?;
-    
-    let mut question = String::new();
-    io::stdin().read_line(&mut question)?;
-    Ok(question.trim().to_string())
-}
-
-fn looks_like_incomplete_input(input: &str) -> bool {
-    // Check for common patterns that indicate shell mangling
-    input.ends_with("'") ||  // Unclosed single quote
-    input.ends_with('"') ||  // Unclosed double quote  
-    input.ends_with("\\") || // Trailing backslash
-    input.is_empty() ||      // Empty input
-    (input.len() < 5 && input.chars().any(|c| "'\"`\\".contains(c))) || // Very short with special chars
-    // Check for patterns that suggest apostrophe was mangled by shell
-    input.contains(" s ") ||     // "what s the" suggests "what's the"
-    input.contains(" t ") ||     // "don t know" suggests "don't know"
-    input.contains(" re ") ||    // "you re right" suggests "you're right"
-    input.contains(" ll ") ||    // "we ll see" suggests "we'll see"
-    input.contains(" ve ") ||    // "I ve got" suggests "I've got"
-    input.contains(" d ") ||     // "I d like" suggests "I'd like"
-    input.ends_with(" s") ||     // "what s" suggests "what's"
-    input.ends_with(" t") ||     // "don t" suggests "don't"
-    input.ends_with(" re") ||    // "you re" suggests "you're"
-    input.ends_with(" ll") ||    // "we ll" suggests "we'll"
-    input.ends_with(" ve") ||    // "I ve" suggests "I've"
-    input.ends_with(" d")        // "I d" suggests "I'd"
-}
-
-fn print_with_typing_effect(text: &str) {
-    for char in text.chars() {
-        print!("{}", char);
-        io::stdout().flush().unwrap();
-        
-        // Fast typing effect - subtle but still human-like
-        let delay = match char {
-            ' ' => Duration::from_millis(2),   // Spaces are very fast
-            '.' | '!' | '?' => Duration::from_millis(150), // Brief pause at sentence endings
-            ',' | ';' | ':' => Duration::from_millis(30),  // Small pause at punctuation
-            '\n' => Duration::from_millis(80),  // Brief pause at line breaks
-            _ => Duration::from_millis(8),      // Regular characters - much faster
-        };
-        
-        std::thread::sleep(delay);
-    }
-    println!(); // Final newline
-}
@@ -0,0 +1,127 @@
+//! Spotting questions the shell mangled. In `qq what's up, it's late` the two
+//! apostrophes pair up as a quoted string, so qq receives `whats up, its` and
+//! `late`. Contractions that lost their apostrophe give it away.
+
+// Contractions whose apostrophe-less form is rarely a word of its own
+const CONTRACTIONS: &[&str] = &[
+    "aren't", "can't", "couldn't", "didn't", "doesn't", "don't", "hadn't", "hasn't", "haven't",
+    "he's", "here's", "how's", "i'm", "i've", "isn't", "it'll", "mustn't", "needn't",
+    "she's", "shouldn't", "that'll", "that's", "there's", "they'd", "they'll", "they're",
+    "they've", "wasn't", "we've", "weren't", "what's", "where's", "who's", "won't", "wouldn't",
+    "you'd", "you'll", "you're", "you've", "ain't", "y'all",
+];
+
+// Contractions that are also ordinary words without the apostrophe
+const AMBIGUOUS: &[&str] = &[
+    "he'd", "he'll", "i'd", "i'll", "it's", "let's", "she'd", "she'll", "we'd", "we'll", "we're",
+];
+
+// Endings the shell can split off as a word of their own: `what s`
+const FRAGMENTS: &[&str] = &["s", "t", "re", "ll", "ve", "d", "m"];
+
+/// Confidence needed before suggesting a fix. Unambiguous contractions
+/// score 2 on their own; ambiguous ones need to sit where a quote was.
+const THRESHOLD: u8 = 2;
+
+/// The question as it was probably typed, if the arguments look like the
+/// shell ate apostrophes. `args` are the arguments exactly as the shell split
+/// them, since which words ended up sharing an argument shows where the
+/// stray quotes were.
+pub fn detect(args: &[String]) -> Option<String> {
+    // One quote pair glues the words between them into a single argument
+    let quoted_span = args.len() > 1;
+
+    let mut words: Vec<String> = Vec::new();
+    let mut changed = false;
+
+    for arg in args {
+        let arg_words: Vec<&str> = arg.split(' ').collect();
+        let spans_words = quoted_span && arg_words.len() > 1;
+        let last = arg_words.len() - 1;
+
+        for (i, word) in arg_words.iter().enumerate() {
+            // The first and last words of a glued argument held the quotes
+            let at_quote = spans_words && (i == 0 || i == last);
+            match fix_joined(word, at_quote) {
+                Some(fixed) => {
+                    words.push(fixed);
+                    changed = true;
+                }
+                None => words.push(word.to_string()),
+            }
+        }
+    }
+
+    // `what s the time`: the ending came through as a separate word
+    let mut merged: Vec<String> = Vec::new();
+    for word in words {
+        if let Some(previous) = merged.last_mut() {
+            if let Some(fixed) = fix_split(previous, &word) {
+                *previous = fixed;
+                changed = true;
+                continue;
+            }
+        }
+        merged.push(word);
+    }
+
+    changed.then(|| merged.join(" "))
+}
+
+/// `whats` → `what's`, keeping surrounding punctuation and capitals.
+fn fix_joined(word: &str, at_quote: bool) -> Option<String> {
+    let start = word.find(|c: char| c.is_alphabetic())?;
+    let end = word.rfind(|c: char| c.is_alphabetic())? + 1;
+    let core = &word[start..end];
+    let lower = core.to_lowercase();
+
+    let (contraction, score) = CONTRACTIONS
+        .iter()
+        .map(|c| (c, 2))
+        .chain(AMBIGUOUS.iter().map(|c| (c, 1)))
+        .find(|(c, _)| c.replace('\'', "") == lower)?;
+    if score + u8::from(at_quote) < THRESHOLD {
+        return None;
+    }
+
+    let fixed = match_case(core, contraction);
+    Some(format!("{}{}{}", &word[..start], fixed, &word[end..]))
+}
+
+/// `don` + `t` → `don't`, but only when that makes a known contraction.
+fn fix_split(previous: &str, word: &str) -> Option<String> {
+    let (fragment, rest) = match word.find(|c: char| !c.is_alphabetic()) {
+        Some(i) => word.split_at(i),
+        None => (word, ""),
+    };
+    if !FRAGMENTS.contains(&fragment.to_lowercase().as_str()) || !previous.ends_with(char::is_alphabetic) {
+        return None;
+    }
+
+    let start = previous.rfind(|c: char| !c.is_alphabetic()).map_or(0, |i| i + 1);
+    let candidate = format!("{}'{}", &previous[start..], fragment).to_lowercase();
+    CONTRACTIONS
+        .iter()
+        .chain(AMBIGUOUS)
+        .find(|c| **c == candidate)?;
+
+    Some(format!("{}'{}{}", previous, fragment, rest))
+}
+
+/// Spells `contraction` with the capitals of `typed`: `Dont` → `Don't`.
+fn match_case(typed: &str, contraction: &str) -> String {
+    let mut typed = typed.chars();
+    contraction
+        .chars()
+        .map(|c| {
+            if c == '\'' {
+                return c;
+            }
+            match typed.next() {
+                Some(t) if t.is_uppercase() => c.to_ascii_uppercase(),
+                _ if c == 'i' && contraction.starts_with("i'") => 'I',
+                _ => c,
+            }
+        })
+        .collect()
+}
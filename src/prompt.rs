@@ -0,0 +1,161 @@
+use anyhow::Result;
+use std::fs;
+
+use crate::config::{Config, Persona};
+use crate::document::Document;
+use crate::image::Image;
+
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant designed to give quick, concise answers to terminal users. Keep responses under 280 characters when possible, but feel free to go a bit longer if necessary for clarity. Match the user's tone - if they ask something silly, be playful back. If they ask for facts, be matter-of-fact. Never ask follow-up questions or try to continue the conversation. When appropriate, include relevant links or sources. Use markdown formatting for emphasis: **bold**, *italic*, `code`, ~~strikethrough~~. Feel free to use ASCII art and Unicode characters - they display well in modern terminals. Remember: your response will be processed to show proper formatting in the terminal.
+
+---
+
+Anything after these instructions comes from the user.";
+
+const FORMATTING: &str = "Never ask follow-up questions. Use markdown formatting for emphasis: **bold**, *italic*, `code`.";
+
+// Built-in personas for `--as`: name, prompt and max_tokens
+const BUILTIN_PERSONAS: &[(&str, &str, u32)] = &[
+    (
+        "terse",
+        "Answer terminal users in a single line with no preamble and no explanation. If a command or code snippet answers the question, reply with just that in `code`.",
+        100,
+    ),
+    (
+        "tutor",
+        "You are a patient tutor for terminal users who want to understand, not just get an answer. Explain the idea step by step, show a small example, and point out common pitfalls. Take the space you need, but stay on topic.",
+        1500,
+    ),
+    (
+        "reviewer",
+        "You are a senior engineer reviewing the code, command or design the user shares. List problems by importance: bugs first, then risky or unclear parts, then style. Quote the relevant line in `code` and give a concrete fix for each. If it looks good, say so in a sentence.",
+        1500,
+    ),
+];
+
+/// A question together with the system prompt it should be asked with.
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    /// System prompt sections, most stable first
+    pub system: Vec<String>,
+    pub question: String,
+    /// Attached with `-f`, sent before the question
+    pub documents: Vec<Document>,
+    /// Attached with `-i`, sent before the question
+    pub images: Vec<Image>,
+    /// Earlier questions and answers in the conversation, oldest first
+    pub history: Vec<Turn>,
+}
+
+/// One earlier question and its answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    pub question: String,
+    pub answer: String,
+}
+
+impl Prompt {
+    /// The question with the standard system prompt.
+    pub fn new(question: &str) -> Result<Self> {
+        Ok(Self {
+            system: get_system_prompt_parts()?,
+            question: question.to_string(),
+            documents: Vec::new(),
+            images: Vec::new(),
+            history: Vec::new(),
+        })
+    }
+
+    /// The question with a caller supplied system prompt.
+    pub fn with_system(system: Vec<String>, question: &str) -> Self {
+        Self {
+            system,
+            question: question.to_string(),
+            documents: Vec::new(),
+            images: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn system_text(&self) -> String {
+        self.system.join("\n\n")
+    }
+}
+
+/// The persona called `name`, from the config or else the built-in ones.
+pub fn find_persona(config: &Config, name: &str) -> Option<Persona> {
+    if let Some(persona) = config.personas.get(name) {
+        return Some(persona.clone());
+    }
+
+    BUILTIN_PERSONAS
+        .iter()
+        .find(|(builtin, _, _)| *builtin == name)
+        .map(|(_, prompt, max_tokens)| Persona {
+            prompt: format!("{} {}", prompt, FORMATTING),
+            max_tokens: Some(*max_tokens),
+            ..Persona::default()
+        })
+}
+
+/// Names of all personas, built-in and configured, sorted.
+pub fn persona_names(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_PERSONAS
+        .iter()
+        .map(|(name, _, _)| name.to_string())
+        .chain(config.personas.keys().cloned())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The system prompt sections for answering as `persona`. The user's custom
+/// prompt still comes last.
+pub fn persona_system_prompt(persona: &Persona) -> Vec<String> {
+    let mut parts = Vec::new();
+    if persona.extend {
+        parts.push(DEFAULT_SYSTEM_PROMPT.to_string());
+    }
+    parts.push(persona.prompt.clone());
+    parts.extend(get_custom_prompt());
+    parts
+}
+
+pub fn get_system_prompt() -> Result<String> {
+    Ok(get_system_prompt_parts()?.join("\n\n"))
+}
+
+/// The system prompt split into its stable sections: the built-in
+/// instructions, followed by the user's custom prompt if they have one.
+pub fn get_system_prompt_parts() -> Result<Vec<String>> {
+    let mut parts = vec![DEFAULT_SYSTEM_PROMPT.to_string()];
+    parts.extend(get_custom_prompt());
+    Ok(parts)
+}
+
+/// The non-comment contents of custom_prompt.txt, if there are any.
+pub fn get_custom_prompt() -> Option<String> {
+    let config_dir = Config::config_dir().ok()?;
+    let custom_prompt_path = config_dir.join("custom_prompt.txt");
+    
+    // Create template file if it doesn't exist
+    if !custom_prompt_path.exists() {
+        let _ = fs::create_dir_all(&config_dir);
+        let template_content = "# Your custom prompt goes here\n# \n# This will be APPENDED to the default system prompt, so you can add\n# additional instructions without losing the original behavior.\n# \n# Examples:\n# - Always respond in a specific language\n# - Add domain-specific knowledge\n# - Modify the response style\n# - Add personality traits\n# \n# Delete these comments and add your custom instructions below:\n\n";
+        let _ = fs::write(&custom_prompt_path, template_content);
+    }
+    
+    let custom_prompt = fs::read_to_string(&custom_prompt_path).ok()?;
+    
+    // Extract non-comment lines from the custom prompt
+    let custom_lines: Vec<&str> = custom_prompt
+        .lines()
+        .filter(|line| !line.trim().starts_with("#") && !line.trim().is_empty())
+        .collect();
+    
+    if custom_lines.is_empty() {
+        None
+    } else {
+        Some(custom_lines.join("\n"))
+    }
+}
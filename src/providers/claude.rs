@@ -1,202 +1,349 @@
-use super::Provider;
+use super::{Answer, Delta, OnDelta, Provider, ProviderError, Usage};
+use crate::document::{Content, Document};
+use crate::prompt::Prompt;
 use anyhow::Result;
 use reqwest::Client;
 use serde_json::{json, Value};
-use std::fs;
 
-const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant designed to give quick, concise answers to terminal users. Keep responses under 280 characters when possible, but feel free to go a bit longer if necessary for clarity. Match the user's tone - if they ask something silly, be playful back. If they ask for facts, be matter-of-fact. Never ask follow-up questions or try to continue the conversation. When appropriate, include relevant links or sources. Use markdown formatting for emphasis: **bold**, *italic*, `code`, ~~strikethrough~~. Feel free to use ASCII art and Unicode characters - they display well in modern terminals. Remember: your response will be processed to show proper formatting in the terminal.
+// Anthropic won't cache anything shorter than ~1024 tokens, so don't bother
+// marking smaller inputs.
+const CACHEABLE_INPUT_CHARS: usize = 4096;
 
----
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
 
-Anything after these instructions comes from the user.";
+// Smallest thinking budget the API accepts
+pub const MIN_THINKING_BUDGET: u32 = 1024;
+
+// Short names for the current models, for `fallback` and `--compare`
+const MODEL_ALIASES: &[(&str, &str)] = &[
+    ("opus", "claude-opus-4-1"),
+    ("sonnet", "claude-sonnet-4-5"),
+    ("haiku", "claude-haiku-4-5"),
+];
+
+/// The full model id for a short name like `sonnet`. Anything else is
+/// passed through as is.
+pub fn resolve_model(model: &str) -> &str {
+    MODEL_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(model))
+        .map_or(model, |(_, id)| id)
+}
+
+/// The short names [`resolve_model`] knows, like `sonnet`.
+pub fn model_aliases() -> impl Iterator<Item = &'static str> {
+    MODEL_ALIASES.iter().map(|(alias, _)| *alias)
+}
 
 pub struct ClaudeProvider {
     api_key: String,
     model: String,
+    max_tokens: u32,
+    thinking_budget: Option<u32>,
+    base_url: String,
     client: Client,
 }
 
 impl ClaudeProvider {
-    pub fn new(api_key: String, model: String) -> Self {
+    pub fn new(api_key: String, model: String, max_tokens: u32) -> Self {
         Self {
             api_key,
             model,
+            max_tokens,
+            thinking_budget: None,
+            base_url: DEFAULT_BASE_URL.to_string(),
             client: Client::new(),
         }
     }
-    
-    fn get_system_prompt() -> Result<String> {
-        use crate::config::Config;
-        
-        let mut system_prompt = DEFAULT_SYSTEM_PROMPT.to_string();
-        
-        // Try to append custom prompt from user config directory
-        if let Ok(config_dir) = Config::config_dir() {
-            let custom_prompt_path = config_dir.join("custom_prompt.txt");
-            
-            // Create template file if it doesn't exist
-            if !custom_prompt_path.exists() {
-                let _ = fs::create_dir_all(&config_dir);
-                let template_content = "# Your custom prompt goes here\n# \n# This will be APPENDED to the default system prompt, so you can add\n# additional instructions without losing the original behavior.\n# \n# Examples:\n# - Always respond in a specific language\n# - Add domain-specific knowledge\n# - Modify the response style\n# - Add personality traits\n# \n# Delete these comments and add your custom instructions below:\n\n";
-                let _ = fs::write(&custom_prompt_path, template_content);
-            }
-            
-            if let Ok(custom_prompt) = fs::read_to_string(&custom_prompt_path) {
-                // Extract non-comment lines from the custom prompt
-                let custom_lines: Vec<&str> = custom_prompt
-                    .lines()
-                    .filter(|line| !line.trim().starts_with("#") && !line.trim().is_empty())
-                    .collect();
-                
-                if !custom_lines.is_empty() {
-                    let actual_custom_content = custom_lines.join("\n");
-                    system_prompt.push_str("\n\n");
-                    system_prompt.push_str(&actual_custom_content);
-                }
+
+    /// Sends requests somewhere other than api.anthropic.com, e.g. a proxy
+    /// or a local fake server in tests.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Enables extended thinking with the given token budget. The budget is
+    /// added on top of `max_tokens` so the answer itself isn't squeezed out.
+    pub fn with_thinking(mut self, budget: Option<u32>) -> Self {
+        self.thinking_budget = budget.map(|b| b.max(MIN_THINKING_BUDGET));
+        self
+    }
+
+}
+
+/// Builds the `system` field as text blocks, marking the last one as a cache
+/// breakpoint so the whole (unchanging) prompt can be served from the cache.
+fn system_blocks(parts: &[String]) -> Value {
+    let last = parts.len().saturating_sub(1);
+    let blocks: Vec<Value> = parts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| {
+            if i == last {
+                json!({ "type": "text", "text": text, "cache_control": { "type": "ephemeral" } })
+            } else {
+                json!({ "type": "text", "text": text })
             }
-        }
-        
-        Ok(system_prompt)
+        })
+        .collect();
+    Value::Array(blocks)
+}
+
+/// Large questions (usually piped files or logs) are marked cacheable too,
+/// so follow-up questions about the same input are cheaper. Documents and
+/// images go first, which is where Claude reads them best, and the last
+/// document is a cache breakpoint as well.
+fn user_content(prompt: &Prompt) -> Value {
+    let question = &prompt.question;
+    if prompt.documents.is_empty() && prompt.images.is_empty() && question.len() < CACHEABLE_INPUT_CHARS {
+        return json!(question);
+    }
+
+    let mut blocks: Vec<Value> = prompt.documents.iter().map(document_block).collect();
+    if let Some(last) = blocks.last_mut() {
+        last["cache_control"] = json!({ "type": "ephemeral" });
     }
+    blocks.extend(prompt.images.iter().map(|image| {
+        json!({
+            "type": "image",
+            "source": { "type": "base64", "media_type": image.media_type, "data": image.base64() }
+        })
+    }));
+    if question.len() >= CACHEABLE_INPUT_CHARS {
+        blocks.push(json!({ "type": "text", "text": question, "cache_control": { "type": "ephemeral" } }));
+    } else {
+        blocks.push(json!({ "type": "text", "text": question }));
+    }
+    Value::Array(blocks)
 }
 
-#[async_trait::async_trait]
-impl Provider for ClaudeProvider {
-    async fn ask(&self, question: &str) -> Result<String> {
-        let system_prompt = Self::get_system_prompt()?;
-        
+/// The messages for `prompt`: its earlier turns, then the question.
+fn messages(prompt: &Prompt) -> Vec<Value> {
+    let mut messages = Vec::new();
+    for turn in &prompt.history {
+        messages.push(json!({ "role": "user", "content": turn.question }));
+        messages.push(json!({ "role": "assistant", "content": turn.answer }));
+    }
+    messages.push(json!({ "role": "user", "content": user_content(prompt) }));
+    messages
+}
+
+fn document_block(document: &Document) -> Value {
+    let source = match &document.content {
+        Content::Pdf(_) => json!({ "type": "base64", "media_type": "application/pdf", "data": document.base64() }),
+        Content::Text(text) => json!({ "type": "text", "media_type": "text/plain", "data": text }),
+    };
+    json!({ "type": "document", "source": source, "title": document.title() })
+}
+
+impl ClaudeProvider {
+    fn request_body(&self, prompt: &Prompt, messages: Value, allow_thinking: bool) -> Value {
+        let mut body = json!({
+            "model": self.model,
+            "max_tokens": self.max_tokens,
+            "system": system_blocks(&prompt.system),
+            "messages": messages
+        });
+
+        if let (Some(budget), true) = (self.thinking_budget, allow_thinking) {
+            body["max_tokens"] = json!(self.max_tokens + budget);
+            body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget });
+        }
+        body
+    }
+
+    async fn post(&self, body: &Value) -> Result<reqwest::Response> {
         let response = self.client
-            .post("https://api.anthropic.com/v1/messages")
+            .post(format!("{}/v1/messages", self.base_url))
             .header("Content-Type", "application/json")
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
-            .json(&json!({
-                "model": self.model,
-                "max_tokens": 300,
-                "system": system_prompt,
-                "messages": [
-                    {
-                        "role": "user",
-                        "content": question
-                    }
-                ]
-            }))
+            .json(body)
             .send()
-            .await?;
+            .await
+            .map_err(|e| ProviderError::Network(e.to_string()))?;
             
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("API request failed with status {}: {}", status, error_text));
+            let message = format!("API request failed with status {}: {}", status, error_text);
+            return Err(ProviderError::from_status(status.as_u16(), message).into());
         }
-        
-        let json: Value = response.json().await?;
-        
-        let content = json["content"][0]["text"]
-            .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Unexpected response format"))?;
-            
-        Ok(format_for_terminal(content))
+        Ok(response)
+    }
+
+    async fn send(&self, prompt: &Prompt, messages: Value, allow_thinking: bool) -> Result<Answer> {
+        let response = self.post(&self.request_body(prompt, messages, allow_thinking)).await?;
+        let json: Value = response
+            .json()
+            .await
+            .map_err(|e| ProviderError::Other(format!("Unreadable response: {}", e)))?;
+        parse_response(&json)
+    }
+}
+
+#[async_trait::async_trait]
+impl Provider for ClaudeProvider {
+    async fn ask(&self, prompt: &Prompt) -> Result<Answer> {
+        self.send(prompt, json!(messages(prompt)), true).await
+    }
+
+    async fn ask_streaming(&self, prompt: &Prompt, on_delta: &OnDelta<'_>) -> Result<Answer> {
+        let mut body = self.request_body(prompt, json!(messages(prompt)), true);
+        body["stream"] = json!(true);
+        let mut response = self.post(&body).await?;
+
+        // Server-sent events, separated by blank lines. Chunks can end
+        // anywhere, even inside a character, so split the raw bytes.
+        let mut pending = Vec::new();
+        let mut stream = StreamedAnswer::default();
+        while let Some(chunk) = response.chunk().await.map_err(|e| ProviderError::Network(e.to_string()))? {
+            pending.extend_from_slice(&chunk);
+            while let Some(end) = pending.windows(2).position(|w| w == b"\n\n") {
+                let event: Vec<u8> = pending.drain(..end + 2).collect();
+                stream.apply(&String::from_utf8_lossy(&event), on_delta)?;
+            }
+        }
+        stream.finish()
+    }
+
+    async fn continue_answer(&self, prompt: &Prompt, partial: &str) -> Result<Answer> {
+        // Prefilling the assistant turn makes Claude pick up where it left
+        // off. The API rejects trailing whitespace there, and doesn't allow
+        // prefill together with extended thinking.
+        let mut messages = messages(prompt);
+        // Thinking can use up every token before any text comes out, and an
+        // empty prefill is rejected too, so then just ask again without it
+        if !partial.trim().is_empty() {
+            messages.push(json!({ "role": "assistant", "content": partial.trim_end() }));
+        }
+        self.send(prompt, json!(messages), false).await
+    }
+
+    fn reads_pdfs(&self) -> bool {
+        true
     }
 }
 
-fn format_for_terminal(text: &str) -> String {
-    let mut result = text.to_string();
-    
-    // Handle code blocks - remove ``` markers
-    result = result.replace("```", "");
-    
-    // Convert Markdown bold to ANSI bold (**text**)
-    result = replace_markdown_pattern(&result, "**", "\x1b[1m", "\x1b[0m");
-    
-    // Convert Markdown code spans to highlighted text (`code`)
-    result = replace_markdown_pattern(&result, "`", "\x1b[93m", "\x1b[0m");
-    
-    // Convert strikethrough (~~text~~)
-    result = replace_markdown_pattern(&result, "~~", "\x1b[9m", "\x1b[0m");
-    
-    // Convert Markdown italic to ANSI italic (*text*) - do this last to avoid conflicts
-    result = replace_single_asterisk_italic(&result);
-    
-    result
+/// An answer being put together from streamed events.
+#[derive(Default)]
+struct StreamedAnswer {
+    text: String,
+    thinking: String,
+    stop_reason: Option<String>,
+    usage: Usage,
 }
 
-fn replace_markdown_pattern(text: &str, marker: &str, start_ansi: &str, end_ansi: &str) -> String {
-    let mut result = String::new();
-    let chars = text.chars().collect::<Vec<_>>();
-    let mut i = 0;
-    
-    while i < chars.len() {
-        if i + marker.len() <= chars.len() && 
-           chars[i..i + marker.len()].iter().collect::<String>() == marker {
-            // Found opening marker, look for closing marker
-            let mut j = i + marker.len();
-            while j + marker.len() <= chars.len() {
-                if chars[j..j + marker.len()].iter().collect::<String>() == marker {
-                    // Found closing marker
-                    let content: String = chars[i + marker.len()..j].iter().collect();
-                    result.push_str(start_ansi);
-                    result.push_str(&content);
-                    result.push_str(end_ansi);
-                    i = j + marker.len();
-                    break;
+impl StreamedAnswer {
+    fn apply(&mut self, event: &str, on_delta: &OnDelta<'_>) -> Result<()> {
+        let Some(data) = event.lines().find_map(|line| line.strip_prefix("data:")) else {
+            return Ok(());
+        };
+        let Ok(data) = serde_json::from_str::<Value>(data.trim()) else {
+            return Ok(());
+        };
+        let count = |value: &Value| value.as_u64().unwrap_or(0) as u32;
+
+        match data["type"].as_str() {
+            Some("message_start") => {
+                let usage = &data["message"]["usage"];
+                self.usage.input_tokens = count(&usage["input_tokens"]);
+                self.usage.cache_creation_input_tokens = count(&usage["cache_creation_input_tokens"]);
+                self.usage.cache_read_input_tokens = count(&usage["cache_read_input_tokens"]);
+            }
+            Some("content_block_delta") => {
+                let delta = &data["delta"];
+                if let Some(text) = delta["text"].as_str() {
+                    self.text.push_str(text);
+                    on_delta(Delta::Text(text));
+                } else if let Some(thinking) = delta["thinking"].as_str() {
+                    self.thinking.push_str(thinking);
+                    on_delta(Delta::Thinking(thinking));
+                }
+            }
+            Some("message_delta") => {
+                if let Some(stop_reason) = data["delta"]["stop_reason"].as_str() {
+                    self.stop_reason = Some(stop_reason.to_string());
                 }
-                j += 1;
+                self.usage.output_tokens = count(&data["usage"]["output_tokens"]);
             }
-            if j + marker.len() > chars.len() {
-                // No closing marker found, just add the character
-                result.push(chars[i]);
-                i += 1;
+            Some("error") => {
+                let message = format!("Stream failed: {}", data["error"]);
+                // Errors mid-stream don't come with a status, so go by their type
+                return Err(match data["error"]["type"].as_str() {
+                    Some("overloaded_error") => ProviderError::Overloaded(message),
+                    Some("rate_limit_error") => ProviderError::RateLimited(message),
+                    _ => ProviderError::Other(message),
+                }
+                .into());
             }
-        } else {
-            result.push(chars[i]);
-            i += 1;
+            _ => {}
         }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Answer> {
+        if self.text.is_empty() && self.stop_reason.as_deref() != Some("max_tokens") {
+            return Err(anyhow::anyhow!(
+                "Response contained no text (stop_reason: {})",
+                self.stop_reason.as_deref().unwrap_or("unknown")
+            ));
+        }
+        Ok(Answer {
+            text: self.text,
+            thinking: (!self.thinking.is_empty()).then_some(self.thinking),
+            stop_reason: self.stop_reason,
+            usage: self.usage,
+        })
     }
-    
-    result
 }
 
-fn replace_single_asterisk_italic(text: &str) -> String {
-    let mut result = String::new();
-    let chars: Vec<char> = text.chars().collect();
-    let mut i = 0;
-    
-    while i < chars.len() {
-        if chars[i] == '*' {
-            // Check if it's not part of ** (already processed)
-            let prev_is_asterisk = i > 0 && chars[i - 1] == '*';
-            let next_is_asterisk = i + 1 < chars.len() && chars[i + 1] == '*';
-            
-            if !prev_is_asterisk && !next_is_asterisk {
-                // Look for closing single asterisk
-                let mut j = i + 1;
-                while j < chars.len() {
-                    if chars[j] == '*' && (j + 1 >= chars.len() || chars[j + 1] != '*') {
-                        // Found closing single asterisk
-                        let content: String = chars[i + 1..j].iter().collect();
-                        result.push_str("\x1b[3m");
-                        result.push_str(&content);
-                        result.push_str("\x1b[0m");
-                        i = j + 1;
-                        break;
-                    }
-                    j += 1;
-                }
-                if j >= chars.len() {
-                    // No closing found
-                    result.push(chars[i]);
-                    i += 1;
+fn parse_response(json: &Value) -> Result<Answer> {
+    let blocks = json["content"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("Unexpected response format"))?;
+
+    let mut text = String::new();
+    let mut thinking = Vec::new();
+
+    // With thinking enabled the reasoning comes before the answer, and long
+    // answers can be split over several text blocks, so walk all of them
+    for block in blocks {
+        match block["type"].as_str() {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or_default()),
+            Some("thinking") => {
+                if let Some(reasoning) = block["thinking"].as_str() {
+                    thinking.push(reasoning.to_string());
                 }
-            } else {
-                result.push(chars[i]);
-                i += 1;
             }
-        } else {
-            result.push(chars[i]);
-            i += 1;
+            Some("redacted_thinking") => thinking.push("[some reasoning was redacted]".to_string()),
+            // Tool use and anything newer isn't something we can show
+            _ => {}
         }
     }
-    
-    result
+
+    let stop_reason = json["stop_reason"].as_str().map(|s| s.to_string());
+
+    // An empty answer is only expected if we ran out of tokens
+    if text.is_empty() && stop_reason.as_deref() != Some("max_tokens") {
+        return Err(anyhow::anyhow!(
+            "Response contained no text (stop_reason: {})",
+            stop_reason.as_deref().unwrap_or("unknown")
+        ));
+    }
+
+    let usage = Usage {
+        input_tokens: json["usage"]["input_tokens"].as_u64().unwrap_or(0) as u32,
+        output_tokens: json["usage"]["output_tokens"].as_u64().unwrap_or(0) as u32,
+        cache_creation_input_tokens: json["usage"]["cache_creation_input_tokens"].as_u64().unwrap_or(0) as u32,
+        cache_read_input_tokens: json["usage"]["cache_read_input_tokens"].as_u64().unwrap_or(0) as u32,
+    };
+        
+    Ok(Answer {
+        text,
+        thinking: if thinking.is_empty() { None } else { Some(thinking.join("\n\n")) },
+        stop_reason,
+        usage,
+    })
 }
@@ -0,0 +1,95 @@
+use super::{Answer, Delta, OnDelta, Provider, ProviderError, Target};
+use crate::prompt::Prompt;
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// The configured provider followed by the `fallback` entries. Each request
+/// goes down the chain until one answers, moving on only for failures
+/// another provider might not have (see [`ProviderError::should_fall_back`]).
+pub struct FallbackProvider {
+    chain: Vec<(Target, Box<dyn Provider>)>,
+    /// Index of the provider that gave the last answer
+    answered_by: Mutex<usize>,
+}
+
+impl FallbackProvider {
+    pub fn new(chain: Vec<(Target, Box<dyn Provider>)>) -> Self {
+        Self {
+            chain,
+            answered_by: Mutex::new(0),
+        }
+    }
+
+    /// Records who answered, or decides whether to try the next provider.
+    /// Returns the result to hand back, if the search is over.
+    fn settle(&self, index: usize, result: Result<Answer>, last_error: &mut Option<anyhow::Error>) -> Option<Result<Answer>> {
+        match result {
+            Ok(answer) => {
+                *self.answered_by.lock().unwrap_or_else(|e| e.into_inner()) = index;
+                Some(Ok(answer))
+            }
+            Err(e) if ProviderError::of(&e).is_some_and(ProviderError::should_fall_back) => {
+                *last_error = Some(e);
+                None
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Provider for FallbackProvider {
+    async fn ask(&self, prompt: &Prompt) -> Result<Answer> {
+        let mut last_error = None;
+        for (index, (_, provider)) in self.chain.iter().enumerate() {
+            if let Some(result) = self.settle(index, provider.ask(prompt).await, &mut last_error) {
+                return result;
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No providers configured")))
+    }
+
+    async fn ask_streaming(&self, prompt: &Prompt, on_delta: &OnDelta<'_>) -> Result<Answer> {
+        let streamed = AtomicBool::new(false);
+        let forward = |delta: Delta<'_>| {
+            streamed.store(true, Ordering::SeqCst);
+            on_delta(delta);
+        };
+        let mut last_error = None;
+        for (index, (_, provider)) in self.chain.iter().enumerate() {
+            let result = provider.ask_streaming(prompt, &forward).await;
+            // Part of an answer is already out, so another provider can't take over
+            if result.is_err() && streamed.load(Ordering::SeqCst) {
+                return result;
+            }
+            if let Some(result) = self.settle(index, result, &mut last_error) {
+                return result;
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No providers configured")))
+    }
+
+    async fn continue_answer(&self, prompt: &Prompt, partial: &str) -> Result<Answer> {
+        // Start with whoever wrote the beginning, so the answer reads as one
+        let first = *self.answered_by.lock().unwrap_or_else(|e| e.into_inner());
+        let mut last_error = None;
+        for (index, (_, provider)) in self.chain.iter().enumerate().skip(first) {
+            if let Some(result) = self.settle(index, provider.continue_answer(prompt, partial).await, &mut last_error) {
+                return result;
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No providers configured")))
+    }
+
+    /// Only when every provider in the chain does, since any of them may
+    /// end up with the question.
+    fn reads_pdfs(&self) -> bool {
+        self.chain.iter().all(|(_, provider)| provider.reads_pdfs())
+    }
+
+    fn fell_back_to(&self) -> Option<Target> {
+        let index = *self.answered_by.lock().unwrap_or_else(|e| e.into_inner());
+        (index > 0).then(|| self.chain[index].0.clone())
+    }
+}
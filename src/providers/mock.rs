@@ -0,0 +1,97 @@
+use super::{Answer, Provider, ProviderError, Usage};
+use crate::prompt::Prompt;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fs;
+use std::sync::Mutex;
+
+/// One scripted reply. Either an answer, or an error message to fail with,
+/// typed by the status code in it like a real API error.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MockReply {
+    pub text: String,
+    pub thinking: Option<String>,
+    pub stop_reason: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Offline provider for tests and demos. Replies come from, in order of
+/// preference, the JSON array in `$QQ_MOCK_SCRIPT`, the text in
+/// `$QQ_MOCK_RESPONSE`, or a canned echo of the question.
+pub struct MockProvider {
+    replies: Mutex<VecDeque<MockReply>>,
+    fallback: Option<String>,
+}
+
+impl MockProvider {
+    pub fn new(replies: Vec<MockReply>) -> Self {
+        Self {
+            replies: Mutex::new(replies.into()),
+            fallback: None,
+        }
+    }
+
+    /// Always answers with `text`.
+    pub fn with_response(text: &str) -> Self {
+        Self {
+            replies: Mutex::new(VecDeque::new()),
+            fallback: Some(text.to_string()),
+        }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        if let Ok(path) = std::env::var("QQ_MOCK_SCRIPT") {
+            let content = fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Could not read mock script {}: {}", path, e))?;
+            let replies: Vec<MockReply> = serde_json::from_str(&content)?;
+            return Ok(Self::new(replies));
+        }
+
+        Ok(Self {
+            replies: Mutex::new(VecDeque::new()),
+            fallback: std::env::var("QQ_MOCK_RESPONSE").ok(),
+        })
+    }
+
+    fn next_reply(&self, question: &str) -> Result<Answer> {
+        let scripted = self.replies.lock().unwrap().pop_front();
+        let reply = match scripted {
+            Some(reply) => reply,
+            None => MockReply {
+                text: self
+                    .fallback
+                    .clone()
+                    .unwrap_or_else(|| format!("Mock answer to: {}", question)),
+                ..MockReply::default()
+            },
+        };
+
+        if let Some(error) = reply.error {
+            return Err(ProviderError::from_message(error).into());
+        }
+
+        Ok(Answer {
+            usage: Usage {
+                input_tokens: question.len().div_ceil(4) as u32,
+                output_tokens: reply.text.len().div_ceil(4) as u32,
+                ..Usage::default()
+            },
+            text: reply.text,
+            thinking: reply.thinking,
+            stop_reason: Some(reply.stop_reason.unwrap_or_else(|| "end_turn".to_string())),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Provider for MockProvider {
+    async fn ask(&self, prompt: &Prompt) -> Result<Answer> {
+        self.next_reply(&prompt.question)
+    }
+
+    async fn continue_answer(&self, prompt: &Prompt, _partial: &str) -> Result<Answer> {
+        self.next_reply(&prompt.question)
+    }
+}
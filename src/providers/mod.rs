@@ -1,8 +1,273 @@
 pub mod claude;
+pub mod fallback;
+pub mod mock;
 
 use anyhow::Result;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Once;
+
+use crate::config::Config;
+use crate::prompt::Prompt;
+
+/// Token counts reported by the provider for a single request.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Usage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Prompt tokens written to the provider's prompt cache
+    pub cache_creation_input_tokens: u32,
+    /// Prompt tokens served from the provider's prompt cache
+    pub cache_read_input_tokens: u32,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Answer {
+    pub text: String,
+    /// Extended thinking output, when it was enabled for the request
+    pub thinking: Option<String>,
+    /// Why generation stopped, e.g. "end_turn" or "max_tokens"
+    pub stop_reason: Option<String>,
+    pub usage: Usage,
+}
+
+impl Answer {
+    /// True when the answer was cut off by the token limit.
+    pub fn is_truncated(&self) -> bool {
+        self.stop_reason.as_deref() == Some("max_tokens")
+    }
+}
+
+/// Why a provider couldn't answer, sorted by whether another provider might
+/// have better luck.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderError {
+    /// The provider couldn't be reached at all
+    Network(String),
+    /// Overloaded or otherwise failing on its side (5xx)
+    Overloaded(String),
+    /// Too many requests (429)
+    RateLimited(String),
+    /// The API key was missing or refused (401, 403)
+    Auth(String),
+    /// The request itself was turned down, e.g. for being too long (4xx)
+    Request(String),
+    /// A reply that couldn't be made sense of
+    Other(String),
+}
+
+impl ProviderError {
+    /// Sorts a failed HTTP response by its status code.
+    pub fn from_status(status: u16, message: String) -> Self {
+        match status {
+            401 | 403 => Self::Auth(message),
+            429 => Self::RateLimited(message),
+            500..=599 => Self::Overloaded(message),
+            400..=499 => Self::Request(message),
+            _ => Self::Other(message),
+        }
+    }
+
+    /// Sorts an error known only by its message, going by the status code
+    /// in it if there is one.
+    pub fn from_message(message: String) -> Self {
+        let status = message
+            .split("status ")
+            .nth(1)
+            .and_then(|rest| rest.get(..3))
+            .and_then(|code| code.parse().ok());
+        match status {
+            Some(status) => Self::from_status(status, message),
+            None if message.contains("network") || message.contains("connection") => Self::Network(message),
+            None => Self::Other(message),
+        }
+    }
+
+    /// The typed error behind `error`, if a provider raised it.
+    pub fn of(error: &anyhow::Error) -> Option<&Self> {
+        error.downcast_ref()
+    }
+
+    /// Whether the next provider in a fallback chain should be tried. Not
+    /// for rejected requests: the next provider would turn them down too.
+    pub fn should_fall_back(&self) -> bool {
+        matches!(self, Self::Network(_) | Self::Overloaded(_) | Self::RateLimited(_) | Self::Auth(_))
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Network(message)
+            | Self::Overloaded(message)
+            | Self::RateLimited(message)
+            | Self::Auth(message)
+            | Self::Request(message)
+            | Self::Other(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+/// A piece of a streamed answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delta<'a> {
+    Text(&'a str),
+    /// Extended thinking, which comes before the text
+    Thinking(&'a str),
+}
+
+/// Receives the pieces of a streamed answer.
+pub type OnDelta<'a> = dyn Fn(Delta<'_>) + Send + Sync + 'a;
 
 #[async_trait::async_trait]
-pub trait Provider {
-    async fn ask(&self, question: &str) -> Result<String>;
+pub trait Provider: Send + Sync {
+    async fn ask(&self, prompt: &Prompt) -> Result<Answer>;
+
+    /// Like `ask`, also handing each piece of the answer to `on_delta` as it
+    /// arrives. Providers that can't stream hand over the whole answer at once.
+    async fn ask_streaming(&self, prompt: &Prompt, on_delta: &OnDelta<'_>) -> Result<Answer> {
+        let answer = self.ask(prompt).await?;
+        if let Some(thinking) = &answer.thinking {
+            on_delta(Delta::Thinking(thinking));
+        }
+        on_delta(Delta::Text(&answer.text));
+        Ok(answer)
+    }
+
+    /// Continues a previous answer to `prompt` that was cut off after `partial`.
+    async fn continue_answer(&self, prompt: &Prompt, partial: &str) -> Result<Answer>;
+
+    /// Whether PDFs can be sent as they are, rather than as extracted text.
+    fn reads_pdfs(&self) -> bool {
+        false
+    }
+
+    /// Which entry of the `fallback` chain gave the last answer, when the
+    /// configured provider couldn't.
+    fn fell_back_to(&self) -> Option<Target> {
+        None
+    }
+}
+
+/// A provider and model, written `provider:model`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub provider: String,
+    pub model: String,
+}
+
+impl Target {
+    /// `provider:model`, a provider name alone (with the configured model)
+    /// or a model alone (with the configured provider). Claude models can
+    /// be given as `opus`, `sonnet` or `haiku`.
+    pub fn parse(spec: &str, config: &Config) -> Result<Self> {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            return Err(anyhow::anyhow!("Expected a model or provider:model, got nothing"));
+        }
+        let (provider, model) = match spec.split_once(':') {
+            Some((provider, model)) => (provider, model),
+            None if NAMES.contains(&spec) => (spec, ""),
+            None => (config.provider.as_str(), spec),
+        };
+        if !NAMES.contains(&provider) {
+            return Err(anyhow::anyhow!("Unknown provider '{}' (expected {})", provider, NAMES.join(" or ")));
+        }
+        let model = match (provider, model) {
+            (_, "") => config.model.as_str(),
+            // The API doesn't know short names and answers them with a 404
+            ("claude", model) => claude::resolve_model(model),
+            (_, model) => model,
+        };
+        Ok(Self {
+            provider: provider.to_string(),
+            model: model.to_string(),
+        })
+    }
+
+    /// The configured provider and model.
+    pub fn of(config: &Config) -> Self {
+        Self {
+            provider: config.provider.clone(),
+            model: config.model.clone(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!("{}:{}", self.provider, self.model)
+    }
+
+    /// The config with this provider and model swapped in, and no fallback
+    /// chain of its own.
+    pub fn config(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        config.provider = self.provider.clone();
+        config.model = self.model.clone();
+        config.fallback.clear();
+        config
+    }
+}
+
+/// The values `config.provider` can take.
+pub const NAMES: &[&str] = &["claude", "mock"];
+
+/// Builds the provider named by `config.provider`, backed by the ones in
+/// `config.fallback` if it has any.
+pub fn from_config(config: &Config, thinking_budget: Option<u32>) -> Result<Box<dyn Provider>> {
+    if config.fallback.is_empty() {
+        return single(config, thinking_budget);
+    }
+
+    let mut chain = vec![(Target::of(config), single(config, thinking_budget)?)];
+    let mut skipped = Vec::new();
+    for spec in &config.fallback {
+        // A stand-in qq can't use mustn't stop the configured model answering
+        match Target::parse(spec, config) {
+            Ok(target) => {
+                let provider = single(&target.config(config), thinking_budget)?;
+                chain.push((target, provider));
+            }
+            Err(e) => skipped.push(format!("⚠️  Skipping fallback '{}': {}", spec, e)),
+        }
+    }
+    // Once per run, since batches and the server build a provider per question
+    static WARNED: Once = Once::new();
+    if !skipped.is_empty() {
+        WARNED.call_once(|| eprintln!("{}", skipped.join("\n")));
+    }
+    Ok(Box::new(fallback::FallbackProvider::new(chain)))
+}
+
+fn single(config: &Config, thinking_budget: Option<u32>) -> Result<Box<dyn Provider>> {
+    match config.provider.as_str() {
+        "claude" => {
+            let mut provider = claude::ClaudeProvider::new(
+                config.claude_api_key.clone(),
+                config.model.clone(),
+                config.max_tokens,
+            )
+            .with_thinking(thinking_budget);
+            if let Some(base_url) = &config.claude_base_url {
+                provider = provider.with_base_url(base_url);
+            }
+            Ok(Box::new(provider))
+        }
+        "mock" => Ok(Box::new(mock::MockProvider::from_env()?)),
+        other => Err(anyhow::anyhow!("Unknown provider '{}' (expected claude or mock)", other)),
+    }
 }
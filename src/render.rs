@@ -0,0 +1,327 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::budget;
+use crate::cancel;
+use crate::config::{self, Config, TypingMode};
+use crate::links;
+use crate::providers::{Answer, Usage};
+
+// Longer caps are surely typos, and would only make qq look stuck
+const MAX_TYPING_SECS: f64 = 60.0;
+
+/// How answers are typed out: the pace and the longest it may take.
+#[derive(Debug, Clone, Copy)]
+pub struct Typing {
+    pub mode: TypingMode,
+    pub max: Duration,
+}
+
+impl Typing {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            mode: config.typing,
+            // A cap that isn't a number (NaN) gets the default instead
+            max: Duration::try_from_secs_f64(config.typing_max_secs.clamp(0.0, MAX_TYPING_SECS))
+                .unwrap_or_else(|_| Duration::from_secs_f64(config::default_typing_max_secs())),
+        }
+    }
+}
+
+pub fn print_answer(answer: &Answer, show_thinking: bool, typing: Typing) {
+    if show_thinking {
+        if let Some(thinking) = &answer.thinking {
+            println!();
+            for line in thinking.lines() {
+                println!("\x1b[2m  │ {}\x1b[0m", line);
+            }
+        }
+    }
+    print!("\n\x1b[36m  › \x1b[0m"); // Cyan chevron with indent
+    io::stdout().flush().unwrap();
+
+    let hyperlinks = links::supported();
+    let mut urls = Vec::new();
+    let text = links::render(&answer.text, &mut urls, hyperlinks);
+    print_with_typing_effect(&format_for_terminal(&text), typing);
+    print_links(&urls, 0, hyperlinks);
+}
+
+pub fn print_truncation_notice(max_tokens: u32, continuing: bool) {
+    if continuing {
+        eprintln!("\x1b[33m  ✂️  Answer truncated at {} tokens, continuing...\x1b[0m", max_tokens);
+    } else {
+        eprintln!(
+            "\x1b[33m  ✂️  Answer truncated at {} tokens. Use --more to continue automatically, or raise max_tokens in your config.\x1b[0m",
+            max_tokens
+        );
+    }
+}
+
+/// Prints the rest of a truncated answer. Its links are numbered on from
+/// the ones in `so_far`.
+pub fn print_continuation(so_far: &str, text: &str, typing: Typing) {
+    print!("\x1b[36m  … \x1b[0m");
+    io::stdout().flush().unwrap();
+
+    let hyperlinks = links::supported();
+    let mut urls = links::find(so_far);
+    let earlier = urls.len();
+    let text = links::render(text, &mut urls, hyperlinks);
+    print_with_typing_effect(&format_for_terminal(&text), typing);
+    print_links(&urls, earlier, hyperlinks);
+}
+
+/// The numbered list of links under an answer, from the `first` one on.
+fn print_links(urls: &[String], first: usize, hyperlinks: bool) {
+    if urls.len() <= first {
+        return;
+    }
+    println!();
+    for (index, url) in urls.iter().enumerate().skip(first) {
+        let shown = if hyperlinks { links::hyperlink(url, url) } else { url.clone() };
+        println!("\x1b[2m  [{}] {}\x1b[0m", index + 1, shown);
+    }
+}
+
+pub fn print_usage(model: &str, usage: &Usage) {
+    eprintln!(
+        "\x1b[2m  {} · {} in · {} out · {} cache write · {} cache read · ${:.4}\x1b[0m",
+        model,
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cache_creation_input_tokens,
+        usage.cache_read_input_tokens,
+        budget::cost_of(model, usage)
+    );
+}
+
+pub fn print_json(question: &str, model: &str, answer: &Answer, cached: bool) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(&answer_json(question, model, answer, cached))?);
+    Ok(())
+}
+
+/// An answer as JSON, for `--json` and `qq serve`.
+pub fn answer_json(question: &str, model: &str, answer: &Answer, cached: bool) -> serde_json::Value {
+    // Cached answers didn't cost anything this time around
+    let usage = if cached { None } else { Some(&answer.usage) };
+    serde_json::json!({
+        "question": question,
+        "answer": answer.text,
+        "thinking": answer.thinking,
+        "model": model,
+        "cached": cached,
+        "stop_reason": answer.stop_reason,
+        "truncated": answer.is_truncated(),
+        "usage": usage,
+        "cost_usd": usage.map(|u| budget::cost_of(model, u)),
+    })
+}
+
+/// Prints `text` a character at a time, unless stdout isn't a terminal.
+/// Ctrl-C or any key prints the rest at once.
+pub fn print_with_typing_effect(text: &str, typing: Typing) {
+    if typing.mode == TypingMode::Off || !io::stdout().is_terminal() {
+        println!("{}", text);
+        return;
+    }
+
+    cancel::set_typing(true);
+    let start = Instant::now();
+    let mut due = Duration::ZERO;
+    let mut printed = 0;
+    for (chunk, delay) in typing_plan(text, typing) {
+        if cancel::skip_typing() {
+            break;
+        }
+        print!("{}", chunk);
+        io::stdout().flush().unwrap();
+        printed += chunk.len();
+
+        // Sleep until the chunk's due time, so overhead doesn't add up
+        due += delay;
+        if let Some(wait) = due.checked_sub(start.elapsed()) {
+            std::thread::sleep(wait);
+        }
+    }
+    print!("{}", &text[printed..]);
+    cancel::set_typing(false);
+    println!(); // Final newline
+}
+
+/// Splits `text` into what to print and how long to wait after each piece.
+/// Escape sequences stay whole and take no time, and the delays shrink to
+/// fit the whole answer into `typing.max`.
+pub fn typing_plan(text: &str, typing: Typing) -> Vec<(&str, Duration)> {
+    let mut plan = Vec::new();
+    let mut rest = text;
+    while let Some(char) = rest.chars().next() {
+        let len = if char == '\x1b' { escape_len(rest) } else { char.len_utf8() };
+        let (chunk, after) = rest.split_at(len);
+        let delay = if char == '\x1b' {
+            Duration::ZERO
+        } else {
+            char_delay(typing.mode, char, after.chars().next())
+        };
+        plan.push((chunk, delay));
+        rest = after;
+    }
+
+    let total: Duration = plan.iter().map(|(_, delay)| *delay).sum();
+    if total > typing.max {
+        let scale = typing.max.as_secs_f64() / total.as_secs_f64();
+        for (_, delay) in &mut plan {
+            *delay = delay.mul_f64(scale);
+        }
+    }
+    plan
+}
+
+/// Length of the escape sequence `text` starts with: up to the final letter
+/// for styles, or up to the terminator for OSC sequences like hyperlinks.
+fn escape_len(text: &str) -> usize {
+    if text[1..].starts_with(']') {
+        return text
+            .find("\x1b\\")
+            .map(|end| end + 2)
+            .or_else(|| text.find('\x07').map(|end| end + 1))
+            .unwrap_or(text.len());
+    }
+    text[1..]
+        .find(|c: char| c.is_ascii_alphabetic())
+        .map_or(text.len(), |end| end + 2)
+}
+
+fn char_delay(mode: TypingMode, char: char, next: Option<char>) -> Duration {
+    // Only pause at the end of a sentence, not at 3.14 or foo.bar()
+    let ends_sentence = next.is_none_or(char::is_whitespace);
+    let millis = match (mode, char) {
+        (TypingMode::Off, _) => 0,
+        (TypingMode::Fast, '\n') => 10,
+        (TypingMode::Fast, _) => 2,
+        (TypingMode::Natural, ' ') => 2,   // Spaces are very fast
+        (TypingMode::Natural, '.' | '!' | '?') if ends_sentence => 150, // Brief pause at sentence endings
+        (TypingMode::Natural, ',' | ';' | ':') if ends_sentence => 30,  // Small pause at punctuation
+        (TypingMode::Natural, '\n') => 80,  // Brief pause at line breaks
+        (TypingMode::Natural, _) => 8,      // Regular characters
+    };
+    Duration::from_millis(millis)
+}
+
+/// The answer as it reads on screen, without markdown or escape codes.
+pub fn plain_text(answer: &str) -> String {
+    let text = links::inline(answer);
+    strip_ansi(&format_for_terminal(&text)).trim().to_string()
+}
+
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start + escape_len(&rest[start..])..];
+    }
+    plain.push_str(rest);
+    plain
+}
+
+pub fn format_for_terminal(text: &str) -> String {
+    let mut result = text.to_string();
+    
+    // Handle code blocks - remove ``` markers
+    result = result.replace("```", "");
+    
+    // Convert Markdown bold to ANSI bold (**text**)
+    result = replace_markdown_pattern(&result, "**", "\x1b[1m", "\x1b[0m");
+    
+    // Convert Markdown code spans to highlighted text (`code`)
+    result = replace_markdown_pattern(&result, "`", "\x1b[93m", "\x1b[0m");
+    
+    // Convert strikethrough (~~text~~)
+    result = replace_markdown_pattern(&result, "~~", "\x1b[9m", "\x1b[0m");
+    
+    // Convert Markdown italic to ANSI italic (*text*) - do this last to avoid conflicts
+    result = replace_single_asterisk_italic(&result);
+    
+    result
+}
+
+pub fn replace_markdown_pattern(text: &str, marker: &str, start_ansi: &str, end_ansi: &str) -> String {
+    let mut result = String::new();
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut i = 0;
+    
+    while i < chars.len() {
+        if i + marker.len() <= chars.len() && 
+           chars[i..i + marker.len()].iter().collect::<String>() == marker {
+            // Found opening marker, look for closing marker
+            let mut j = i + marker.len();
+            while j + marker.len() <= chars.len() {
+                if chars[j..j + marker.len()].iter().collect::<String>() == marker {
+                    // Found closing marker
+                    let content: String = chars[i + marker.len()..j].iter().collect();
+                    result.push_str(start_ansi);
+                    result.push_str(&content);
+                    result.push_str(end_ansi);
+                    i = j + marker.len();
+                    break;
+                }
+                j += 1;
+            }
+            if j + marker.len() > chars.len() {
+                // No closing marker found, just add the character
+                result.push(chars[i]);
+                i += 1;
+            }
+        } else {
+            result.push(chars[i]);
+            i += 1;
+        }
+    }
+    
+    result
+}
+
+pub fn replace_single_asterisk_italic(text: &str) -> String {
+    let mut result = String::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+    
+    while i < chars.len() {
+        if chars[i] == '*' {
+            // Check if it's not part of ** (already processed)
+            let prev_is_asterisk = i > 0 && chars[i - 1] == '*';
+            let next_is_asterisk = i + 1 < chars.len() && chars[i + 1] == '*';
+            
+            if !prev_is_asterisk && !next_is_asterisk {
+                // Look for closing single asterisk
+                let mut j = i + 1;
+                while j < chars.len() {
+                    if chars[j] == '*' && (j + 1 >= chars.len() || chars[j + 1] != '*') {
+                        // Found closing single asterisk
+                        let content: String = chars[i + 1..j].iter().collect();
+                        result.push_str("\x1b[3m");
+                        result.push_str(&content);
+                        result.push_str("\x1b[0m");
+                        i = j + 1;
+                        break;
+                    }
+                    j += 1;
+                }
+                if j >= chars.len() {
+                    // No closing found
+                    result.push(chars[i]);
+                    i += 1;
+                }
+            } else {
+                result.push(chars[i]);
+                i += 1;
+            }
+        } else {
+            result.push(chars[i]);
+            i += 1;
+        }
+    }
+    
+    result
+}
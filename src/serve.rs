@@ -0,0 +1,647 @@
+//! `qq serve`: the configured provider, system prompt, cache and budget
+//! behind a small HTTP API, so editor plugins and dashboards can ask
+//! questions without holding an API key of their own.
+//!
+//! Every connection carries one request. Bodies are JSON and so are the
+//! replies, except for streamed answers, which are server-sent events.
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::budget::{self, InFlight, Ledger};
+use crate::cache::Cache;
+use crate::config::Config;
+use crate::prompt::{self, Prompt, Turn};
+use crate::providers::{self, claude::MIN_THINKING_BUDGET, Answer, Delta, Provider, ProviderError, Target, Usage};
+use crate::render;
+
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+// Comments sent while waiting, so proxies don't give up on a quiet stream
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+const DEFAULT_HISTORY: usize = 20;
+// Clients get this long to send their request before being turned away
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Connections beyond this wait to be accepted until others finish
+const MAX_CONNECTIONS: usize = 64;
+
+/// A failed request: the status to reply with and why.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Upstream trouble is the gateway's problem (502), except for limits
+    /// and requests the provider turned down, which the caller can act on.
+    fn from_provider(error: anyhow::Error) -> Self {
+        let status = match ProviderError::of(&error) {
+            Some(ProviderError::RateLimited(_)) => 429,
+            Some(ProviderError::Request(_)) => 400,
+            _ => 502,
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Options {
+    model: Option<String>,
+    max_tokens: Option<u32>,
+    #[serde(rename = "as")]
+    persona: Option<String>,
+    stream: bool,
+    no_cache: bool,
+}
+
+#[derive(Deserialize)]
+struct AskBody {
+    question: String,
+    #[serde(flatten)]
+    options: Options,
+}
+
+#[derive(Deserialize)]
+struct ChatBody {
+    messages: Vec<Message>,
+    #[serde(flatten)]
+    options: Options,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+/// A request turned into what to ask and how.
+struct Question {
+    endpoint: String,
+    prompt: Prompt,
+    config: Config,
+    thinking_budget: Option<u32>,
+    stream: bool,
+    cache_key: Option<String>,
+}
+
+pub struct Server {
+    config: Config,
+    token: Option<String>,
+    cache: Option<Cache>,
+    /// Held while appending to the history, which requests share
+    history: Mutex<PathBuf>,
+    /// Requests sent to the provider but not yet in the ledger
+    in_flight: Mutex<InFlight>,
+}
+
+/// A request counted in [`Server::in_flight`] until it's dropped.
+struct Running<'a> {
+    in_flight: &'a Mutex<InFlight>,
+    estimate: f64,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        in_flight.requests -= 1;
+        in_flight.cost -= self.estimate;
+    }
+}
+
+impl Server {
+    pub fn new(config: Config, token: Option<String>) -> Result<Self> {
+        let cache = if config.cache.enabled { Cache::open(&config.cache).ok() } else { None };
+        Ok(Self {
+            config,
+            token,
+            cache,
+            history: Mutex::new(Config::cache_dir()?.join("serve_history.jsonl")),
+            in_flight: Mutex::new(InFlight::default()),
+        })
+    }
+
+    /// Answers connections on `listener` until the process is stopped.
+    pub async fn run(self, listener: TcpListener) -> Result<()> {
+        let server = Arc::new(self);
+        let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+        loop {
+            let permit = Arc::clone(&connections).acquire_owned().await?;
+            let (stream, _) = listener.accept().await?;
+            let server = Arc::clone(&server);
+            tokio::spawn(async move {
+                server.handle(stream).await;
+                drop(permit);
+            });
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream) {
+        let started = Instant::now();
+        // Checked before auth, so idle clients mustn't hold a connection open
+        let read = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+            .await
+            .unwrap_or_else(|_| Err(ApiError::new(408, "Timed out waiting for the request")));
+        let request = match read {
+            Ok(request) => request,
+            Err(e) => {
+                let _ = respond_error(&mut stream, &e).await;
+                return;
+            }
+        };
+
+        let status = match self.route(&request, &mut stream).await {
+            Ok(Some(reply)) => {
+                let _ = respond(&mut stream, 200, &reply).await;
+                200
+            }
+            // Streamed replies are already written
+            Ok(None) => 200,
+            Err(e) => {
+                let _ = respond_error(&mut stream, &e).await;
+                e.status
+            }
+        };
+        eprintln!(
+            "\x1b[2m  {} {} {} {:.1}s\x1b[0m",
+            request.method,
+            request.path,
+            status,
+            started.elapsed().as_secs_f64()
+        );
+    }
+
+    async fn route(&self, request: &Request, stream: &mut TcpStream) -> Result<Option<Value>, ApiError> {
+        self.check_access(request)?;
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/health") => Ok(Some(json!({
+                "ok": true,
+                "provider": self.config.provider,
+                "model": self.config.model,
+            }))),
+            ("GET", "/history") => self.history(request).map(Some),
+            ("POST", "/ask" | "/chat") => {
+                let question = self.question(request)?;
+                if question.stream {
+                    self.stream(question, stream).await;
+                    Ok(None)
+                } else {
+                    self.answer(question).await.map(Some)
+                }
+            }
+            (_, "/health" | "/history" | "/ask" | "/chat") => Err(ApiError::new(405, "Method not allowed")),
+            _ => Err(ApiError::new(404, "Not found: try POST /ask, POST /chat or GET /history")),
+        }
+    }
+
+    /// The token when there is one. Without one, only plain local clients
+    /// get in: browsers could otherwise be talked into spending your credit
+    /// by any page you visit.
+    fn check_access(&self, request: &Request) -> Result<(), ApiError> {
+        if let Some(token) = &self.token {
+            let given = request.header("authorization").and_then(|value| value.strip_prefix("Bearer "));
+            if !given.is_some_and(|given| same(given.trim().as_bytes(), token.as_bytes())) {
+                return Err(ApiError::new(401, "Missing or wrong token (send Authorization: Bearer <token>)"));
+            }
+            return Ok(());
+        }
+
+        if request.header("origin").is_some() {
+            return Err(ApiError::new(403, "Requests from browsers need the server to be started with --token"));
+        }
+        let host = request.header("host").unwrap_or_default();
+        let name = host.rsplit_once(':').map_or(host, |(name, port)| {
+            if port.chars().all(|c| c.is_ascii_digit()) { name } else { host }
+        });
+        if !["localhost", "127.0.0.1", "[::1]"].contains(&name) {
+            return Err(ApiError::new(403, "Only local requests are allowed without --token"));
+        }
+        Ok(())
+    }
+
+    fn question(&self, request: &Request) -> Result<Question, ApiError> {
+        let json_body = request
+            .header("content-type")
+            .is_some_and(|value| value.trim_start().starts_with("application/json"));
+        if !json_body {
+            return Err(ApiError::new(415, "Send the body as JSON (Content-Type: application/json)"));
+        }
+        let invalid = |e: serde_json::Error| ApiError::new(400, format!("Invalid body: {}", e));
+
+        let (question, history, options) = if request.path == "/chat" {
+            let body: ChatBody = serde_json::from_slice(&request.body).map_err(invalid)?;
+            let (question, history) = conversation(body.messages)?;
+            (question, history, body.options)
+        } else {
+            let body: AskBody = serde_json::from_slice(&request.body).map_err(invalid)?;
+            (body.question, Vec::new(), body.options)
+        };
+        if question.trim().is_empty() {
+            return Err(ApiError::new(400, "The question is empty"));
+        }
+
+        let mut config = self.config.clone();
+        let mut prompt = Prompt::new(&question).map_err(|e| ApiError::new(500, e.to_string()))?;
+        prompt.history = history;
+        if let Some(name) = &options.persona {
+            let persona = prompt::find_persona(&config, name).ok_or_else(|| {
+                let names = prompt::persona_names(&config).join(", ");
+                ApiError::new(400, format!("No persona named '{}' (available: {})", name, names))
+            })?;
+            prompt.system = prompt::persona_system_prompt(&persona);
+            persona.apply(&mut config);
+        }
+        // Written as in --compare, so `sonnet` and `claude:haiku` work too
+        if let Some(model) = options.model {
+            let target = Target::parse(&model, &config).map_err(|e| ApiError::new(400, e.to_string()))?;
+            config.provider = target.provider;
+            config.model = target.model;
+        }
+        if let Some(max_tokens) = options.max_tokens {
+            config.max_tokens = max_tokens;
+        }
+
+        let thinking_budget = config
+            .thinking_budget
+            .filter(|budget| *budget > 0)
+            .map(|budget| budget.max(MIN_THINKING_BUDGET));
+        // The same key as on the command line, so the two share answers
+        let cache_key = (self.cache.is_some() && !options.no_cache).then(|| {
+            let mut params = format!("max_tokens={};thinking={:?}", config.max_tokens, thinking_budget);
+            if !prompt.history.is_empty() {
+                params.push_str(&format!(";history={}", history_digest(&prompt.history)));
+            }
+            Cache::key(&config.provider, &config.model, &params, &prompt.system_text(), &question)
+        });
+
+        let stream = options.stream
+            || request
+                .header("accept")
+                .is_some_and(|value| value.contains("text/event-stream"));
+        Ok(Question {
+            endpoint: request.path.clone(),
+            prompt,
+            config,
+            thinking_budget,
+            stream,
+            cache_key,
+        })
+    }
+
+    fn cached(&self, question: &Question) -> Option<Answer> {
+        let entry = self.cache.as_ref()?.get(question.cache_key.as_ref()?)?;
+        Some(Answer {
+            text: entry.text,
+            thinking: entry.thinking,
+            stop_reason: entry.stop_reason,
+            usage: Usage::default(),
+        })
+    }
+
+    /// Checks the budget, then builds the provider to ask. There's nobody
+    /// to ask whether to go over, so going over is refused. Requests still
+    /// running count as if they'd cost their whole estimate, until the
+    /// returned [`Running`] is dropped.
+    fn provider(&self, question: &Question) -> Result<(Box<dyn Provider>, Running<'_>), ApiError> {
+        let ledger = Ledger::load().map_err(|e| ApiError::new(500, e.to_string()))?;
+        let history_chars: usize = question
+            .prompt
+            .history
+            .iter()
+            .map(|turn| turn.question.len() + turn.answer.len())
+            .sum();
+        let prompt_chars = question.prompt.system_text().len() + question.prompt.question.len() + history_chars;
+        let max_output_tokens = question.config.max_tokens + question.thinking_budget.unwrap_or(0);
+        let estimate = budget::estimate_cost(&question.config.model, prompt_chars, max_output_tokens);
+        let provider =
+            providers::from_config(&question.config, question.thinking_budget).map_err(|e| ApiError::new(500, e.to_string()))?;
+
+        // Checked and counted under one lock, so parallel requests can't
+        // all squeeze under the limit at once
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(reason) = budget::check_in_flight(&question.config.budget, &ledger, *in_flight, estimate, budget::now()) {
+            return Err(ApiError::new(429, reason));
+        }
+        in_flight.requests += 1;
+        in_flight.cost += estimate;
+        let running = Running {
+            in_flight: &self.in_flight,
+            estimate,
+        };
+        Ok((provider, running))
+    }
+
+    /// Records a fresh answer in the ledger, cache and history, and returns
+    /// it as JSON.
+    fn finish(&self, question: &Question, provider: &dyn Provider, answer: &Answer) -> Value {
+        let fell_back_to = provider.fell_back_to();
+        let model = fell_back_to.as_ref().map_or(&question.config.model, |target| &target.model);
+        if let Err(e) = Ledger::load().and_then(|mut ledger| ledger.record(model, &answer.usage)) {
+            eprintln!("⚠️  Could not record usage: {}", e);
+        }
+        if let (Some(cache), Some(key)) = (&self.cache, &question.cache_key) {
+            let _ = cache.put_answer(key, &question.prompt.question, provider, answer);
+        }
+        self.remember(question, model, answer, false)
+    }
+
+    async fn answer(&self, question: Question) -> Result<Value, ApiError> {
+        if let Some(answer) = self.cached(&question) {
+            return Ok(self.remember(&question, &question.config.model, &answer, true));
+        }
+        let (provider, _running) = self.provider(&question)?;
+        let answer = provider.ask(&question.prompt).await.map_err(ApiError::from_provider)?;
+        Ok(self.finish(&question, provider.as_ref(), &answer))
+    }
+
+    /// Sends the answer as server-sent events: `thinking` and `delta` for
+    /// each piece of thinking and text, then `done` with the same JSON `/ask`
+    /// replies with, or `error`.
+    async fn stream(&self, question: Question, stream: &mut TcpStream) {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+        if stream.write_all(head.as_bytes()).await.is_err() {
+            return;
+        }
+
+        if let Some(answer) = self.cached(&question) {
+            let done = self.remember(&question, &question.config.model, &answer, true);
+            if let Some(thinking) = &answer.thinking {
+                let _ = send_event(stream, "thinking", &json!({ "text": thinking })).await;
+            }
+            let _ = send_event(stream, "delta", &json!({ "text": answer.text })).await;
+            let _ = send_event(stream, "done", &done).await;
+            return;
+        }
+        let (provider, _running) = match self.provider(&question) {
+            Ok(provider) => provider,
+            Err(e) => {
+                let _ = send_event(stream, "error", &json!({ "error": e.message, "status": e.status })).await;
+                return;
+            }
+        };
+
+        // Each piece goes out as the event it belongs in
+        let (sender, mut pieces) = mpsc::unbounded_channel::<(&str, String)>();
+        let on_delta = move |delta: Delta<'_>| {
+            let _ = match delta {
+                Delta::Text(text) => sender.send(("delta", text.to_string())),
+                Delta::Thinking(thinking) => sender.send(("thinking", thinking.to_string())),
+            };
+        };
+        let request = provider.ask_streaming(&question.prompt, &on_delta);
+        tokio::pin!(request);
+
+        // A client that goes away still leaves an answer to pay for, so it's
+        // seen through and recorded either way
+        let mut connected = true;
+        let result = loop {
+            tokio::select! {
+                result = &mut request => break result,
+                Some((event, text)) = pieces.recv() => {
+                    if connected {
+                        connected = send_event(stream, event, &json!({ "text": text })).await.is_ok();
+                    }
+                }
+                _ = tokio::time::sleep(KEEP_ALIVE) => {
+                    if connected {
+                        connected = stream.write_all(b": waiting\n\n").await.is_ok();
+                    }
+                }
+            }
+        };
+        while let Ok((event, text)) = pieces.try_recv() {
+            if connected {
+                connected = send_event(stream, event, &json!({ "text": text })).await.is_ok();
+            }
+        }
+
+        let (event, data) = match result {
+            Ok(answer) => ("done", self.finish(&question, provider.as_ref(), &answer)),
+            Err(e) => {
+                let e = ApiError::from_provider(e);
+                ("error", json!({ "error": e.message, "status": e.status }))
+            }
+        };
+        if connected {
+            let _ = send_event(stream, event, &data).await;
+        }
+    }
+
+    /// Adds an answer to the history and returns it as JSON.
+    fn remember(&self, question: &Question, model: &str, answer: &Answer, cached: bool) -> Value {
+        let reply = render::answer_json(&question.prompt.question, model, answer, cached);
+        let entry = json!({
+            "timestamp": budget::now(),
+            "endpoint": question.endpoint,
+            "question": question.prompt.question,
+            "model": model,
+            "answer": answer.text,
+            "cached": cached,
+        });
+
+        let path = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&*path))
+            .and_then(|mut file| writeln!(file, "{}", entry));
+        if let Err(e) = written {
+            eprintln!("⚠️  Could not save history: {}", e);
+        }
+        reply
+    }
+
+    /// The latest questions and answers, oldest first.
+    fn history(&self, request: &Request) -> Result<Value, ApiError> {
+        let limit = match request.query_param("limit") {
+            Some(limit) => limit
+                .parse()
+                .map_err(|_| ApiError::new(400, "limit should be a number"))?,
+            None => DEFAULT_HISTORY,
+        };
+        let path = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let content = fs::read_to_string(&*path).unwrap_or_default();
+        let entries: Vec<Value> = content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+        let entries = &entries[entries.len().saturating_sub(limit)..];
+        Ok(json!({ "entries": entries }))
+    }
+}
+
+/// Splits chat messages into earlier turns and the question to answer.
+/// They must alternate user and assistant, ending with the user.
+fn conversation(messages: Vec<Message>) -> Result<(String, Vec<Turn>), ApiError> {
+    let mut history = Vec::new();
+    let mut messages = messages.into_iter();
+    loop {
+        let Some(user) = messages.next() else {
+            return Err(ApiError::new(400, "messages should end with one from the user"));
+        };
+        if user.role != "user" {
+            return Err(ApiError::new(400, "messages should alternate user and assistant, starting with user"));
+        }
+        match messages.next() {
+            None => return Ok((user.content, history)),
+            Some(reply) if reply.role == "assistant" => history.push(Turn {
+                question: user.content,
+                answer: reply.content,
+            }),
+            Some(_) => return Err(ApiError::new(400, "messages should alternate user and assistant, starting with user")),
+        }
+    }
+}
+
+fn history_digest(history: &[Turn]) -> String {
+    let mut hasher = Sha256::new();
+    for turn in history {
+        hasher.update(turn.question.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(turn.answer.as_bytes());
+        hasher.update([0u8]);
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compares without stopping at the first difference, so the time taken
+/// doesn't give the token away.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Reads one request: the head up to the blank line, then `Content-Length`
+/// bytes of body.
+pub async fn read_request(stream: &mut TcpStream) -> Result<Request, ApiError> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if buffer.len() > MAX_HEAD_BYTES {
+            return Err(ApiError::new(431, "Request headers too large"));
+        }
+        let read = stream.read(&mut chunk).await.map_err(|e| ApiError::new(400, e.to_string()))?;
+        if read == 0 {
+            return Err(ApiError::new(400, "Incomplete request"));
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = std::str::from_utf8(&buffer[..head_end]).map_err(|_| ApiError::new(400, "Headers aren't UTF-8"))?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err(ApiError::new(400, "Malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+        body: buffer[head_end + 4..].to_vec(),
+    };
+    let length: usize = match request.header("content-length") {
+        Some(length) => length.parse().map_err(|_| ApiError::new(400, "Bad Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(ApiError::new(413, "Request body too large"));
+    }
+    while request.body.len() < length {
+        let read = stream.read(&mut chunk).await.map_err(|e| ApiError::new(400, e.to_string()))?;
+        if read == 0 {
+            return Err(ApiError::new(400, "Incomplete body"));
+        }
+        request.body.extend_from_slice(&chunk[..read]);
+    }
+    request.body.truncate(length);
+    Ok(request)
+}
+
+async fn respond(stream: &mut TcpStream, status: u16, body: &Value) -> std::io::Result<()> {
+    let body = body.to_string();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn respond_error(stream: &mut TcpStream, error: &ApiError) -> std::io::Result<()> {
+    respond(stream, error.status, &json!({ "error": error.message })).await
+}
+
+async fn send_event(stream: &mut TcpStream, event: &str, data: &Value) -> std::io::Result<()> {
+    stream.write_all(format!("event: {}\ndata: {}\n\n", event, data).as_bytes()).await?;
+    stream.flush().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        502 => "Bad Gateway",
+        _ => "Internal Server Error",
+    }
+}
@@ -0,0 +1,260 @@
+use clap::ValueEnum;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        // Accept paths like /usr/bin/zsh as well as bare names
+        let name = Path::new(name.trim()).file_name()?.to_str()?;
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+
+    /// The user's shell: `$QQ_SHELL` (set by `qq init` snippets), then `$SHELL`.
+    pub fn detect() -> Option<Self> {
+        ["QQ_SHELL", "SHELL"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find_map(|value| Self::from_name(&value))
+    }
+
+    /// Snippet for the shell's rc file that binds Ctrl-G, defines `qq!` and
+    /// `qqrun`, and records failed commands in `state_dir` for `qq why`.
+    pub fn init_script(self, state_dir: &Path) -> String {
+        let script = match self {
+            Shell::Bash => BASH_INIT,
+            Shell::Zsh => ZSH_INIT,
+            Shell::Fish => FISH_INIT,
+        };
+        script.replace("__QQ_STATE_DIR__", &single_quote(self, &state_dir.to_string_lossy()))
+    }
+}
+
+fn single_quote(shell: Shell, value: &str) -> String {
+    match shell {
+        Shell::Fish => format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'")),
+        _ => format!("'{}'", value.replace('\'', "'\\''")),
+    }
+}
+
+/// System prompt for `qq --cmd`, where the answer replaces the shell buffer.
+pub fn command_prompt(shell: Option<Shell>) -> String {
+    let shell = shell.map(|s| s.name()).unwrap_or("a POSIX shell");
+    format!(
+        "You turn requests into shell commands for {}. The user's text is either a description of what they want or a command to fix or complete. Reply with exactly one command (use pipes or && if needed) and nothing else: no explanation, no markdown, no code fences, no leading $.",
+        shell
+    )
+}
+
+/// Pulls the command out of an answer, in case the model wrapped it in a
+/// code fence or backticks anyway.
+pub fn extract_command(answer: &str) -> String {
+    let mut text = answer.trim();
+
+    if let Some(start) = text.find("```") {
+        let after = &text[start + 3..];
+        // Skip the language tag on the opening fence
+        let body = after.split_once('\n').map(|(_, rest)| rest).unwrap_or(after);
+        text = body.split("```").next().unwrap_or(body).trim();
+    }
+
+    let text = text.trim_matches('`').trim();
+    text.strip_prefix("$ ").unwrap_or(text).to_string()
+}
+
+const BASH_INIT: &str = r#"# qq shell integration for bash. Add this to ~/.bashrc:
+#   eval "$(qq init bash)"
+
+# Ctrl-G: turn the current command line into a command suggested by qq
+__qq_cmd() {
+    [ -z "$READLINE_LINE" ] && return
+    local suggestion
+    suggestion="$(QQ_SHELL=bash command qq --cmd "$READLINE_LINE" 2>/dev/null)" || return
+    if [ -n "$suggestion" ]; then
+        READLINE_LINE="$suggestion"
+        READLINE_POINT=${#READLINE_LINE}
+    fi
+}
+bind -x '"\C-g": __qq_cmd'
+
+# Remember the last command and its exit status for qq!, and failures for qq why
+__qq_state_dir=__QQ_STATE_DIR__
+__qq_record() {
+    local status=$?
+    local last
+    last="$(HISTTIMEFORMAT= builtin history 1 | sed 's/^ *[0-9]* *//')"
+    case "$last" in
+        "qq!"*|"qq why"*) ;;
+        *)
+            __qq_last_status=$status
+            __qq_last_command="$last"
+            __qq_save_failure "$status" "$last"
+            ;;
+    esac
+    return $status
+}
+PROMPT_COMMAND="__qq_record${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+
+__qq_save_failure() {
+    # Captured stderr only belongs to a failed qqrun
+    case "$2" in
+        qqrun\ *) [ "$1" -eq 0 ] && rm -f "$__qq_state_dir/last_failure.stderr" ;;
+        *) [ "$1" -ne 0 ] && rm -f "$__qq_state_dir/last_failure.stderr" ;;
+    esac
+    [ "$1" -eq 0 ] && return
+    mkdir -p "$__qq_state_dir" 2>/dev/null
+    printf '%s\n%s\n%s\n' "$1" "$PWD" "$2" > "$__qq_state_dir/last_failure" 2>/dev/null
+}
+
+# qqrun <command>: run a command, keeping its stderr for qq why
+qqrun() {
+    mkdir -p "$__qq_state_dir" 2>/dev/null
+    "$@" 2> >(tee "$__qq_state_dir/last_failure.stderr" >&2)
+}
+
+# qq! [question]: ask about the last command you ran
+qq!() {
+    if [ -z "$__qq_last_command" ]; then
+        echo "qq: no previous command recorded yet" >&2
+        return 1
+    fi
+    command qq "I ran \`$__qq_last_command\` in bash and it exited with status $__qq_last_status. ${*:-What happened?}"
+}
+"#;
+
+const ZSH_INIT: &str = r#"# qq shell integration for zsh. Add this to ~/.zshrc:
+#   eval "$(qq init zsh)"
+
+# Ctrl-G: turn the current command line into a command suggested by qq
+__qq_cmd() {
+    [[ -z "$BUFFER" ]] && return
+    local suggestion
+    suggestion="$(QQ_SHELL=zsh command qq --cmd "$BUFFER" 2>/dev/null)" || return
+    if [[ -n "$suggestion" ]]; then
+        BUFFER="$suggestion"
+        CURSOR=${#BUFFER}
+    fi
+    zle redisplay
+}
+zle -N __qq_cmd
+bindkey '^G' __qq_cmd
+
+# Remember the last command and its exit status for qq!, and failures for qq why
+__qq_state_dir=__QQ_STATE_DIR__
+__qq_preexec() {
+    [[ "$1" == qq!* || "$1" == "qq why"* ]] || __qq_pending_command="$1"
+}
+__qq_precmd() {
+    local qq_status=$?
+    if [[ -n "$__qq_pending_command" ]]; then
+        __qq_last_status=$qq_status
+        __qq_last_command="$__qq_pending_command"
+        __qq_pending_command=
+        __qq_save_failure "$__qq_last_status" "$__qq_last_command"
+    fi
+}
+__qq_save_failure() {
+    # Captured stderr only belongs to a failed qqrun
+    if [[ "$2" == "qqrun "* ]]; then
+        (( $1 == 0 )) && rm -f "$__qq_state_dir/last_failure.stderr"
+    else
+        (( $1 != 0 )) && rm -f "$__qq_state_dir/last_failure.stderr"
+    fi
+    (( $1 == 0 )) && return
+    mkdir -p "$__qq_state_dir" 2>/dev/null
+    printf '%s\n%s\n%s\n' "$1" "$PWD" "$2" > "$__qq_state_dir/last_failure" 2>/dev/null
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook preexec __qq_preexec
+# Run first so $? still belongs to the user's command
+precmd_functions=(__qq_precmd ${precmd_functions:#__qq_precmd})
+
+# qqrun <command>: run a command, keeping its stderr for qq why
+qqrun() {
+    mkdir -p "$__qq_state_dir" 2>/dev/null
+    "$@" 2> >(tee "$__qq_state_dir/last_failure.stderr" >&2)
+}
+
+# qq! [question]: ask about the last command you ran
+function qq! {
+    if [[ -z "$__qq_last_command" ]]; then
+        echo "qq: no previous command recorded yet" >&2
+        return 1
+    fi
+    command qq "I ran \`$__qq_last_command\` in zsh and it exited with status $__qq_last_status. ${*:-What happened?}"
+}
+"#;
+
+const FISH_INIT: &str = r#"# qq shell integration for fish. Add this to ~/.config/fish/config.fish:
+#   qq init fish | source
+
+# Ctrl-G: turn the current command line into a command suggested by qq
+function __qq_cmd
+    set -l buffer (commandline)
+    test -z "$buffer"; and return
+    set -l suggestion (env QQ_SHELL=fish qq --cmd "$buffer" 2>/dev/null)
+    or return
+    if test -n "$suggestion"
+        commandline -r -- (string join \n $suggestion)
+    end
+    commandline -f repaint
+end
+bind \cg __qq_cmd
+
+# Remember the last command and its exit status for qq!, and failures for qq why
+set -g __qq_state_dir __QQ_STATE_DIR__
+function __qq_record --on-event fish_postexec
+    set -l last_status $status
+    string match -q -r '^(qq!|qq why)' -- $argv[1]; and return
+    set -g __qq_last_status $last_status
+    set -g __qq_last_command $argv[1]
+
+    # Captured stderr only belongs to a failed qqrun
+    if string match -q 'qqrun *' -- $argv[1]
+        test $last_status -eq 0; and rm -f $__qq_state_dir/last_failure.stderr
+    else
+        test $last_status -ne 0; and rm -f $__qq_state_dir/last_failure.stderr
+    end
+    test $last_status -eq 0; and return
+    mkdir -p $__qq_state_dir 2>/dev/null
+    printf '%s\n%s\n%s\n' $last_status $PWD $argv[1] > $__qq_state_dir/last_failure 2>/dev/null
+end
+
+# qqrun <command>: run a command, keeping its stderr for qq why
+function qqrun
+    mkdir -p $__qq_state_dir 2>/dev/null
+    $argv 2>| tee $__qq_state_dir/last_failure.stderr >&2
+    return $pipestatus[1]
+end
+
+# qq! [question]: ask about the last command you ran
+function qq!
+    if test -z "$__qq_last_command"
+        echo "qq: no previous command recorded yet" >&2
+        return 1
+    end
+    set -l question "What happened?"
+    if test (count $argv) -gt 0
+        set question "$argv"
+    end
+    command qq "I ran `$__qq_last_command` in fish and it exited with status $__qq_last_status. $question"
+end
+"#;
@@ -0,0 +1,239 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::prompt::{self, Prompt};
+
+const NEW_TEMPLATE: &str = "---
+# Everything between the --- lines is optional
+description: What this template is for
+# model: claude-3-5-haiku-20241022
+# max_tokens: 500
+# system: You are a meticulous code reviewer.
+#
+# In the prompt below, {{input}} is the question or piped text, and
+# placeholders like {{lang}} are filled with --var lang=de
+---
+Replace this line with your prompt.
+
+{{input}}
+";
+
+/// A named prompt from `config_dir/templates/<name>.md`: optional front
+/// matter between `---` lines, then the prompt body with `{{input}}` and
+/// `{{var}}` placeholders.
+#[derive(Debug, Clone, Default)]
+pub struct Template {
+    pub name: String,
+    pub description: Option<String>,
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    /// Replaces the built-in system prompt; the custom prompt still applies
+    pub system: Option<String>,
+    pub body: String,
+}
+
+impl Template {
+    /// Where the template called `name` lives. Names that could point
+    /// outside the templates directory are refused.
+    pub fn path(name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") || name.starts_with('.') {
+            return Err(anyhow::anyhow!("'{}' isn't a valid template name", name));
+        }
+        Ok(Config::templates_dir()?.join(format!("{}.md", name)))
+    }
+
+    pub fn load(name: &str) -> Result<Self> {
+        let path = Self::path(name)?;
+        let content = fs::read_to_string(&path).map_err(|_| {
+            anyhow::anyhow!("No template named '{}' (looked for {})", name, path.display())
+        })?;
+        Self::parse(name, &content)
+    }
+
+    /// Names of all saved templates, sorted.
+    pub fn names() -> Vec<String> {
+        let Ok(entries) = Config::templates_dir().and_then(|dir| Ok(fs::read_dir(dir)?)) else {
+            return Vec::new();
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Writes a starter template, refusing to overwrite an existing one.
+    pub fn create(name: &str) -> Result<PathBuf> {
+        let path = Self::path(name)?;
+        if path.exists() {
+            return Err(anyhow::anyhow!("Template '{}' already exists at {}", name, path.display()));
+        }
+        fs::create_dir_all(Config::templates_dir()?)?;
+        fs::write(&path, NEW_TEMPLATE)?;
+        Ok(path)
+    }
+
+    pub fn parse(name: &str, content: &str) -> Result<Self> {
+        let mut template = Self {
+            name: name.to_string(),
+            ..Self::default()
+        };
+
+        let Some((front_matter, body)) = split_front_matter(content) else {
+            template.body = content.trim().to_string();
+            return Ok(template);
+        };
+        template.body = body.trim().to_string();
+
+        for (key, value) in parse_front_matter(front_matter) {
+            match key.as_str() {
+                "description" => template.description = Some(value),
+                "model" => template.model = Some(value),
+                "system" => template.system = Some(value),
+                "max_tokens" => {
+                    template.max_tokens = Some(value.parse().map_err(|_| {
+                        anyhow::anyhow!("Template '{}': max_tokens must be a number, got '{}'", name, value)
+                    })?)
+                }
+                _ => return Err(anyhow::anyhow!("Template '{}': unknown front matter key '{}'", name, key)),
+            }
+        }
+        Ok(template)
+    }
+
+    pub fn uses_input(&self) -> bool {
+        placeholders(&self.body).any(|name| name == "input")
+    }
+
+    /// The body with its placeholders filled in. Input for a template
+    /// without `{{input}}` is appended after the body.
+    pub fn fill(&self, input: &str, vars: &HashMap<String, String>) -> Result<String> {
+        let mut filled = String::new();
+        let mut missing: Vec<&str> = Vec::new();
+        let mut rest = self.body.as_str();
+
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else { break };
+            let name = rest[start + 2..start + len].trim();
+            filled.push_str(&rest[..start]);
+            match vars.get(name) {
+                _ if name == "input" => filled.push_str(input.trim()),
+                Some(value) => filled.push_str(value),
+                None => {
+                    if !missing.contains(&name) {
+                        missing.push(name);
+                    }
+                }
+            }
+            rest = &rest[start + len + 2..];
+        }
+        filled.push_str(rest);
+
+        if !missing.is_empty() {
+            let flags: Vec<String> = missing.iter().map(|name| format!("--var {}=...", name)).collect();
+            return Err(anyhow::anyhow!("Template '{}' needs {}", self.name, flags.join(" ")));
+        }
+        if !self.uses_input() && !input.trim().is_empty() {
+            filled.push_str("\n\n");
+            filled.push_str(input.trim());
+        }
+        Ok(filled)
+    }
+
+    pub fn to_prompt(&self, input: &str, vars: &HashMap<String, String>) -> Result<Prompt> {
+        let question = self.fill(input, vars)?;
+        let system = match &self.system {
+            Some(system) => {
+                let mut parts = vec![system.clone()];
+                parts.extend(prompt::get_custom_prompt());
+                parts
+            }
+            None => prompt::get_system_prompt_parts()?,
+        };
+        Ok(Prompt::with_system(system, &question))
+    }
+
+    /// Applies the template's model and max_tokens on top of the config.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(model) = &self.model {
+            config.model = model.clone();
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            config.max_tokens = max_tokens;
+        }
+    }
+}
+
+fn split_front_matter(content: &str) -> Option<(&str, &str)> {
+    let rest = content.strip_prefix("---")?;
+    let rest = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n'))?;
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// `key: value` lines, with `key: |` starting an indented multi-line value.
+fn parse_front_matter(front_matter: &str) -> Vec<(String, String)> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    let mut block: Option<(String, Vec<String>)> = None;
+
+    for line in front_matter.lines() {
+        if let Some((_, lines)) = &mut block {
+            if line.starts_with([' ', '\t']) || line.trim().is_empty() {
+                lines.push(line.trim().to_string());
+                continue;
+            }
+            let (key, lines) = block.take().unwrap();
+            pairs.push((key, lines.join("\n").trim().to_string()));
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let (key, value) = (key.trim().to_string(), value.trim());
+        if value == "|" {
+            block = Some((key, Vec::new()));
+        } else {
+            pairs.push((key, unquote(value).to_string()));
+        }
+    }
+
+    if let Some((key, lines)) = block {
+        pairs.push((key, lines.join("\n").trim().to_string()));
+    }
+    pairs
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+            return inner;
+        }
+    }
+    value
+}
+
+/// Names of the `{{placeholders}}` in a template body, in order.
+fn placeholders(body: &str) -> impl Iterator<Item = &str> {
+    body.split("{{")
+        .skip(1)
+        .filter_map(|part| part.split_once("}}"))
+        .map(|(name, _)| name.trim())
+}
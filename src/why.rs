@@ -0,0 +1,111 @@
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::prompt::{self, Prompt};
+
+const WHY_SYSTEM_PROMPT: &str = "You diagnose failed shell commands for terminal users. You'll get the command, its exit status, the working directory and, when available, its error output. Explain the most likely cause in a sentence or two, then give the fix, ideally as a command in `code`. Use what exit statuses conventionally mean (127: command not found, 126: not executable, 130: interrupted with Ctrl-C, 137: killed, often out of memory). If the output isn't enough to be sure, say what to check rather than guessing. Never ask follow-up questions. Use markdown formatting for emphasis: **bold**, *italic*, `code`.";
+
+// Only the tail of long error output is sent; that's where the error usually is
+const MAX_OUTPUT_CHARS: usize = 4000;
+
+/// The last failed command, as recorded by the `qq init` shell hook.
+#[derive(Debug, Clone)]
+pub struct LastFailure {
+    pub status: i32,
+    pub cwd: String,
+    pub command: String,
+    /// Error output, from `qqrun` or piped into `qq why`
+    pub output: Option<String>,
+}
+
+impl LastFailure {
+    /// Where the shell hook writes the failure. The file holds the exit
+    /// status, the working directory and then the command, one per line.
+    pub fn path() -> Result<PathBuf> {
+        let mut path = Config::cache_dir()?;
+        path.push("last_failure");
+        Ok(path)
+    }
+
+    /// Captured stderr of the failed command, written by `qqrun`.
+    pub fn output_path() -> Result<PathBuf> {
+        let mut path = Config::cache_dir()?;
+        path.push("last_failure.stderr");
+        Ok(path)
+    }
+
+    pub fn load() -> Result<Option<Self>> {
+        let content = match fs::read_to_string(Self::path()?) {
+            Ok(content) => content,
+            Err(_) => return Ok(None),
+        };
+
+        let mut lines = content.lines();
+        let status = lines.next().and_then(|s| s.trim().parse().ok());
+        let cwd = lines.next().unwrap_or_default().to_string();
+        let command = lines.collect::<Vec<_>>().join("\n");
+
+        let Some(status) = status else {
+            return Ok(None);
+        };
+        if command.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let output = fs::read_to_string(Self::output_path()?)
+            .ok()
+            .filter(|output| !output.trim().is_empty());
+
+        Ok(Some(Self { status, cwd, command, output }))
+    }
+
+    /// Builds the diagnosis request, keeping the user's custom prompt.
+    pub fn to_prompt(&self, shell: Option<&str>) -> Prompt {
+        why_prompt(&self.describe(shell))
+    }
+
+    fn describe(&self, shell: Option<&str>) -> String {
+        let mut question = format!(
+            "This command failed:\n\n{}\n\nExit status: {}\nWorking directory: {}\n",
+            self.command, self.status, self.cwd
+        );
+        if let Some(shell) = shell {
+            question.push_str(&format!("Shell: {}\n", shell));
+        }
+
+        match &self.output {
+            Some(output) => question.push_str(&format!("\nError output:\n{}\n", tail(output))),
+            None => question.push_str("\nThe error output wasn't captured.\n"),
+        }
+
+        question.push_str("\nWhy did it fail and how do I fix it?");
+        question
+    }
+}
+
+/// Diagnosis request for error output piped into `qq why`. The pipeline's
+/// command hasn't finished yet, so the recorded failure can't describe it.
+pub fn output_prompt(output: &str, shell: Option<&str>) -> Prompt {
+    let mut question = format!("A command failed with this output:\n\n{}\n", tail(output));
+    if let Some(shell) = shell {
+        question.push_str(&format!("Shell: {}\n", shell));
+    }
+    question.push_str("\nWhy did it fail and how do I fix it?");
+    why_prompt(&question)
+}
+
+fn why_prompt(question: &str) -> Prompt {
+    let mut system = vec![WHY_SYSTEM_PROMPT.to_string()];
+    system.extend(prompt::get_custom_prompt());
+    Prompt::with_system(system, question)
+}
+
+fn tail(output: &str) -> &str {
+    let output = output.trim();
+    match output.char_indices().rev().nth(MAX_OUTPUT_CHARS) {
+        Some((i, _)) => &output[i..],
+        None => output,
+    }
+}
@@ -0,0 +1,93 @@
+use quick_question::batch::{load_finished, markdown_report, parse_items, BatchItem, BatchResult};
+use quick_question::config::Config;
+use std::fs;
+
+fn answered(index: usize, question: &str) -> BatchResult {
+    BatchResult {
+        index,
+        id: None,
+        question: question.to_string(),
+        model: "m".to_string(),
+        max_tokens: 100,
+        persona: None,
+        answer: Some(format!("About {}", question)),
+        error: None,
+        truncated: false,
+        cost_usd: 0.0,
+    }
+}
+
+#[test]
+fn questions_are_read_as_lines_or_json() {
+    let items = parse_items("What is Rust?\n\n# skipped\n{\"id\": \"py\", \"question\": \"What is Python?\", \"as\": \"terse\", \"max_tokens\": 100}\n").unwrap();
+
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].question, "What is Rust?");
+    assert_eq!(
+        items[1],
+        BatchItem {
+            id: Some("py".to_string()),
+            question: "What is Python?".to_string(),
+            model: None,
+            max_tokens: Some(100),
+            persona: Some("terse".to_string()),
+        }
+    );
+}
+
+#[test]
+fn bad_lines_are_reported_by_number() {
+    let error = parse_items("ok\n{\"question\": \"x\", \"modle\": \"m\"}\n").unwrap_err();
+    assert!(error.to_string().starts_with("Line 2:"), "{}", error);
+
+    let error = parse_items("ok\n\n{\"id\": \"empty\"}\n").unwrap_err();
+    assert_eq!(error.to_string(), "Line 3: no question");
+}
+
+#[test]
+fn only_matching_answers_are_resumed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("answers.jsonl");
+    let mut failed = answered(1, "b");
+    failed.answer = None;
+    failed.error = Some("overloaded".to_string());
+    let mut longer = answered(3, "d");
+    longer.max_tokens = 500;
+    let mut fell_back = answered(4, "e");
+    fell_back.model = "backup".to_string();
+    let lines: Vec<String> = [answered(0, "a"), failed, answered(2, "changed"), longer, fell_back, answered(5, "f")]
+        .iter()
+        .map(|result| serde_json::to_string(result).unwrap())
+        .collect();
+    fs::write(&path, lines.join("\n") + "\n{\"index\": 6, \"quest").unwrap();
+
+    let items = parse_items("a\nb\nc\nd\ne\n{\"question\": \"f\", \"as\": \"terse\"}\ng").unwrap();
+    let config = Config {
+        model: "m".to_string(),
+        max_tokens: 100,
+        ..Config::default()
+    };
+    let finished = load_finished(&path, &items, &config);
+
+    assert_eq!(finished.len(), 1);
+    assert_eq!(finished[0].index, 0);
+}
+
+#[test]
+fn the_report_lists_answers_and_failures() {
+    let mut named = answered(1, "What is Python?");
+    named.id = Some("py".to_string());
+    named.truncated = true;
+    let mut failed = answered(2, "c");
+    failed.answer = None;
+    failed.error = Some("overloaded".to_string());
+
+    let report = markdown_report(&[answered(0, "a"), named, failed]);
+
+    assert_eq!(
+        report,
+        "## a\n\nAbout a\n\n\
+         ## py\n\n> What is Python?\n\nAbout What is Python?\n\n*(answer cut off at max_tokens)*\n\n\
+         ## c\n\n*No answer: overloaded*\n\n"
+    );
+}
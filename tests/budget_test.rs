@@ -0,0 +1,122 @@
+use quick_question::budget::{
+    check, check_in_flight, cost_of, estimate_cost, pricing_for, utc_date, InFlight, Ledger, UsageRecord,
+};
+use quick_question::config::BudgetConfig;
+use quick_question::providers::Usage;
+
+// 2024-02-29 00:00:00 UTC
+const LEAP_DAY: u64 = 1_709_164_800;
+const DAY: u64 = 86_400;
+
+fn spent(timestamp: u64, cost_usd: f64) -> UsageRecord {
+    UsageRecord {
+        timestamp,
+        model: "claude-sonnet-4-5".to_string(),
+        input_tokens: 0,
+        output_tokens: 0,
+        cache_creation_input_tokens: 0,
+        cache_read_input_tokens: 0,
+        cost_usd,
+    }
+}
+
+fn limits(daily_usd: Option<f64>, monthly_usd: Option<f64>, max_requests_per_hour: Option<u32>) -> BudgetConfig {
+    BudgetConfig {
+        daily_usd,
+        monthly_usd,
+        max_requests_per_hour,
+    }
+}
+
+#[test]
+fn prices_follow_the_model_family() {
+    let prices = |model: &str| {
+        let pricing = pricing_for(model);
+        (pricing.input_per_mtok, pricing.output_per_mtok)
+    };
+
+    assert_eq!(prices("claude-opus-4-5"), (5.0, 25.0));
+    assert_eq!(prices("claude-opus-4-1"), (15.0, 75.0));
+    assert_eq!(prices("claude-haiku-4-5"), (1.0, 5.0));
+    assert_eq!(prices("claude-3-5-haiku-20241022"), (0.8, 4.0));
+    assert_eq!(prices("claude-3-haiku-20240307"), (0.25, 1.25));
+    assert_eq!(prices("claude-sonnet-4-5"), (3.0, 15.0));
+    assert_eq!(prices("something-new"), (3.0, 15.0));
+}
+
+#[test]
+fn cache_writes_cost_more_and_reads_less() {
+    let million = |usage: Usage| cost_of("claude-sonnet-4-5", &usage);
+
+    assert_eq!(million(Usage { input_tokens: 1_000_000, ..Usage::default() }), 3.0);
+    assert_eq!(million(Usage { output_tokens: 1_000_000, ..Usage::default() }), 15.0);
+    assert_eq!(million(Usage { cache_creation_input_tokens: 1_000_000, ..Usage::default() }), 3.75);
+    assert!((million(Usage { cache_read_input_tokens: 1_000_000, ..Usage::default() }) - 0.3).abs() < 1e-9);
+}
+
+#[test]
+fn estimates_assume_four_characters_a_token_and_a_full_answer() {
+    // 1000 input tokens and 1000 output tokens at Sonnet prices
+    assert!((estimate_cost("claude-sonnet-4-5", 4000, 1000) - 0.018).abs() < 1e-9);
+    assert_eq!(estimate_cost("claude-sonnet-4-5", 1, 0), estimate_cost("claude-sonnet-4-5", 4, 0));
+}
+
+#[test]
+fn dates_handle_month_and_leap_year_boundaries() {
+    assert_eq!(utc_date(0), (1970, 1, 1));
+    assert_eq!(utc_date(951_782_400), (2000, 2, 29));
+    assert_eq!(utc_date(951_868_800), (2000, 3, 1));
+    assert_eq!(utc_date(LEAP_DAY), (2024, 2, 29));
+    assert_eq!(utc_date(LEAP_DAY + DAY - 1), (2024, 2, 29));
+    assert_eq!(utc_date(LEAP_DAY + DAY), (2024, 3, 1));
+    assert_eq!(utc_date(1_706_745_600 - 1), (2024, 1, 31));
+    assert_eq!(utc_date(1_735_689_600 - 1), (2024, 12, 31));
+    assert_eq!(utc_date(1_735_689_600), (2025, 1, 1));
+    // 2100 isn't a leap year
+    assert_eq!(utc_date(4_107_456_000), (2100, 2, 28));
+    assert_eq!(utc_date(4_107_456_000 + DAY), (2100, 3, 1));
+}
+
+#[test]
+fn daily_limits_reset_at_utc_midnight() {
+    let ledger = Ledger::from_records(vec![spent(LEAP_DAY + 10, 0.9)]);
+    let budget = limits(Some(1.0), None, None);
+
+    let reason = check(&budget, &ledger, 0.2, LEAP_DAY + DAY - 1).unwrap();
+    assert!(reason.contains("daily budget"), "{}", reason);
+    assert!(check(&budget, &ledger, 0.1, LEAP_DAY + DAY - 1).is_none());
+    assert!(check(&budget, &ledger, 0.2, LEAP_DAY + DAY).is_none());
+}
+
+#[test]
+fn monthly_limits_reset_on_the_first() {
+    let ledger = Ledger::from_records(vec![spent(LEAP_DAY - 28 * DAY, 4.0), spent(LEAP_DAY, 4.0)]);
+    let budget = limits(None, Some(10.0), None);
+
+    let reason = check(&budget, &ledger, 2.5, LEAP_DAY + 1).unwrap();
+    assert!(reason.contains("monthly budget"), "{}", reason);
+    assert!(check(&budget, &ledger, 1.5, LEAP_DAY + 1).is_none());
+    // March 1st starts over
+    assert!(check(&budget, &ledger, 2.5, LEAP_DAY + DAY).is_none());
+}
+
+#[test]
+fn hourly_limits_count_the_last_hour() {
+    let now = LEAP_DAY + 7200;
+    let ledger = Ledger::from_records(vec![spent(now - 3600, 0.0), spent(now - 3599, 0.0), spent(now - 10, 0.0)]);
+
+    assert!(check(&limits(None, None, Some(3)), &ledger, 0.0, now).is_none());
+    let reason = check(&limits(None, None, Some(2)), &ledger, 0.0, now).unwrap();
+    assert!(reason.contains("2 requests in the last hour"), "{}", reason);
+}
+
+#[test]
+fn running_requests_count_too() {
+    let ledger = Ledger::from_records(vec![spent(LEAP_DAY, 0.5)]);
+    let running = InFlight { requests: 2, cost: 0.4 };
+
+    assert!(check_in_flight(&limits(Some(1.0), None, None), &ledger, running, 0.05, LEAP_DAY).is_none());
+    let reason = check_in_flight(&limits(Some(1.0), None, None), &ledger, running, 0.2, LEAP_DAY).unwrap();
+    assert!(reason.contains("counting requests still running"), "{}", reason);
+    assert!(check_in_flight(&limits(None, None, Some(3)), &ledger, running, 0.0, LEAP_DAY).is_some());
+}
@@ -0,0 +1,99 @@
+use quick_question::budget;
+use quick_question::cache::{Cache, CacheEntry};
+use quick_question::config::CacheConfig;
+use quick_question::providers::{Answer, Usage};
+use std::fs;
+use tempfile::TempDir;
+
+fn cache(dir: &TempDir, ttl_hours: u64, max_entries: usize) -> Cache {
+    let config = CacheConfig {
+        enabled: true,
+        ttl_hours,
+        max_entries,
+    };
+    Cache::open_in(dir.path().to_path_buf(), &config).unwrap()
+}
+
+fn answer(text: &str) -> Answer {
+    Answer {
+        text: text.to_string(),
+        thinking: None,
+        stop_reason: Some("end_turn".to_string()),
+        usage: Usage::default(),
+    }
+}
+
+/// Saves an entry as if it was created and last used at the given times.
+fn plant(dir: &TempDir, key: &str, created_at: u64, last_used: u64) {
+    let entry = CacheEntry {
+        created_at,
+        last_used,
+        question: key.to_string(),
+        text: format!("answer {}", key),
+        thinking: None,
+        stop_reason: None,
+    };
+    fs::write(dir.path().join(format!("{}.json", key)), serde_json::to_string(&entry).unwrap()).unwrap();
+}
+
+fn exists(dir: &TempDir, key: &str) -> bool {
+    dir.path().join(format!("{}.json", key)).exists()
+}
+
+#[test]
+fn entries_expire_after_the_ttl() {
+    let dir = TempDir::new().unwrap();
+    let cache = cache(&dir, 1, 10);
+    let now = budget::now();
+    plant(&dir, "fresh", now - 3500, now - 3500);
+    plant(&dir, "stale", now - 3700, now - 10);
+
+    assert_eq!(cache.get("fresh").unwrap().text, "answer fresh");
+    assert!(cache.get("stale").is_none());
+    assert!(!exists(&dir, "stale"));
+}
+
+#[test]
+fn hits_mark_entries_as_used() {
+    let dir = TempDir::new().unwrap();
+    let cache = cache(&dir, 1, 10);
+    let now = budget::now();
+    plant(&dir, "old", now - 100, now - 100);
+
+    cache.get("old").unwrap();
+
+    let saved: CacheEntry = serde_json::from_str(&fs::read_to_string(dir.path().join("old.json")).unwrap()).unwrap();
+    assert!(saved.last_used >= now);
+    assert_eq!(saved.created_at, now - 100);
+}
+
+#[test]
+fn the_least_recently_used_entries_are_evicted() {
+    let dir = TempDir::new().unwrap();
+    let cache = cache(&dir, 1, 2);
+    let now = budget::now();
+    plant(&dir, "first", now - 300, now - 300);
+    plant(&dir, "second", now - 200, now - 200);
+    plant(&dir, "expired", now - 4000, now - 1);
+    // Using the oldest entry keeps it over the newer one
+    cache.get("first").unwrap();
+
+    cache.put("third", "third", &answer("3")).unwrap();
+
+    assert!(exists(&dir, "first"));
+    assert!(!exists(&dir, "second"));
+    assert!(!exists(&dir, "expired"));
+    assert!(exists(&dir, "third"));
+}
+
+#[test]
+fn putting_a_key_again_replaces_its_answer() {
+    let dir = TempDir::new().unwrap();
+    let cache = cache(&dir, 1, 10);
+
+    cache.put("key", "question", &answer("old")).unwrap();
+    cache.put("key", "question", &answer("new")).unwrap();
+
+    assert_eq!(cache.get("key").unwrap().text, "new");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+}
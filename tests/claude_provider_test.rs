@@ -0,0 +1,313 @@
+mod common;
+
+use common::{api_error, message, message_with_blocks, FakeAnthropic};
+use quick_question::document::{Content, Document, PageRange};
+use quick_question::image::Image;
+use quick_question::prompt::{Prompt, Turn};
+use quick_question::providers::{claude::ClaudeProvider, Delta, Provider, ProviderError};
+use serde_json::json;
+use std::path::Path;
+
+/// A provider talking to `server`, set up without touching the environment.
+fn provider(server: &FakeAnthropic) -> ClaudeProvider {
+    ClaudeProvider::new("test-key".to_string(), "claude-test".to_string(), 300)
+        .with_base_url(&server.url)
+}
+
+fn prompt(question: &str) -> Prompt {
+    Prompt::with_system(vec!["Be brief.".to_string(), "Be kind.".to_string()], question)
+}
+
+#[tokio::test]
+async fn sends_expected_request() {
+    let server = FakeAnthropic::start(vec![(200, message("Hi!", "end_turn"))]);
+
+    let answer = provider(&server).ask(&prompt("What is Rust?")).await.unwrap();
+
+    assert_eq!(answer.text, "Hi!");
+    assert_eq!(answer.usage.input_tokens, 12);
+    assert_eq!(answer.usage.output_tokens, 34);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/v1/messages");
+    assert_eq!(request.header("x-api-key"), Some("test-key"));
+    assert_eq!(request.header("anthropic-version"), Some("2023-06-01"));
+    assert_eq!(request.body["model"], "claude-test");
+    assert_eq!(request.body["max_tokens"], 300);
+    assert_eq!(request.body["messages"][0]["content"], "What is Rust?");
+    assert!(request.body.get("thinking").is_none());
+
+    // The stable system prompt is marked for prompt caching
+    let system = request.body["system"].as_array().unwrap();
+    assert_eq!(system.len(), 2);
+    assert_eq!(system[0]["text"], "Be brief.");
+    assert!(system[0].get("cache_control").is_none());
+    assert_eq!(system[1]["cache_control"]["type"], "ephemeral");
+}
+
+#[tokio::test]
+async fn large_input_is_marked_cacheable() {
+    let server = FakeAnthropic::start(vec![(200, message("ok", "end_turn"))]);
+    let log = "error: something broke\n".repeat(500);
+
+    provider(&server).ask(&prompt(&log)).await.unwrap();
+
+    let content = &server.requests()[0].body["messages"][0]["content"];
+    assert_eq!(content[0]["text"], log.as_str());
+    assert_eq!(content[0]["cache_control"]["type"], "ephemeral");
+}
+
+#[tokio::test]
+async fn images_are_sent_before_the_question() {
+    let server = FakeAnthropic::start(vec![(200, message("A cat.", "end_turn"))]);
+    let png = [b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".as_slice(), &[0, 0, 0, 2, 0, 0, 0, 1]].concat();
+    let mut prompt = prompt("What is this?");
+    prompt.images.push(Image::from_bytes(Path::new("cat.png"), png).unwrap());
+
+    provider(&server).ask(&prompt).await.unwrap();
+
+    let content = &server.requests()[0].body["messages"][0]["content"];
+    assert_eq!(content[0]["type"], "image");
+    assert_eq!(content[0]["source"]["type"], "base64");
+    assert_eq!(content[0]["source"]["media_type"], "image/png");
+    assert_eq!(content[0]["source"]["data"], "iVBORw0KGgoAAAANSUhEUgAAAAIAAAAB");
+    assert_eq!(content[1]["type"], "text");
+    assert_eq!(content[1]["text"], "What is this?");
+}
+
+#[tokio::test]
+async fn documents_are_sent_as_document_blocks() {
+    let server = FakeAnthropic::start(vec![(200, message("Looks fine.", "end_turn"))]);
+    let mut prompt = prompt("Any problems?");
+    prompt.documents.push(Document {
+        name: "spec.pdf".to_string(),
+        pages: None,
+        content: Content::Pdf(b"%PDF-1.4".to_vec()),
+    });
+    prompt.documents.push(Document {
+        name: "manual.pdf".to_string(),
+        pages: PageRange::parse("3-7"),
+        content: Content::Text("Chapter 2".to_string()),
+    });
+
+    provider(&server).ask(&prompt).await.unwrap();
+
+    let content = &server.requests()[0].body["messages"][0]["content"];
+    assert_eq!(content[0]["type"], "document");
+    assert_eq!(content[0]["source"]["media_type"], "application/pdf");
+    assert_eq!(content[0]["source"]["data"], "JVBERi0xLjQ=");
+    assert_eq!(content[0]["title"], "spec.pdf");
+    assert!(content[0].get("cache_control").is_none());
+    assert_eq!(content[1]["source"]["type"], "text");
+    assert_eq!(content[1]["source"]["data"], "Chapter 2");
+    assert_eq!(content[1]["title"], "manual.pdf (pages 3-7 only)");
+    assert_eq!(content[1]["cache_control"]["type"], "ephemeral");
+    assert_eq!(content[2]["text"], "Any problems?");
+}
+
+#[tokio::test]
+async fn joins_text_blocks_and_skips_the_rest() {
+    let blocks = json!([
+        { "type": "thinking", "thinking": "Let me think.", "signature": "sig" },
+        { "type": "text", "text": "Hello, " },
+        { "type": "tool_use", "id": "tool_1", "name": "search", "input": {} },
+        { "type": "text", "text": "world!" }
+    ]);
+    let server = FakeAnthropic::start(vec![(200, message_with_blocks(blocks, "end_turn"))]);
+
+    let answer = provider(&server).ask(&prompt("hi")).await.unwrap();
+
+    assert_eq!(answer.text, "Hello, world!");
+    assert_eq!(answer.thinking.as_deref(), Some("Let me think."));
+    assert!(!answer.is_truncated());
+}
+
+#[tokio::test]
+async fn reports_truncation() {
+    let server = FakeAnthropic::start(vec![(200, message("It all started", "max_tokens"))]);
+
+    let answer = provider(&server).ask(&prompt("history of unix")).await.unwrap();
+
+    assert!(answer.is_truncated());
+    assert_eq!(answer.stop_reason.as_deref(), Some("max_tokens"));
+}
+
+#[tokio::test]
+async fn thinking_adds_to_max_tokens() {
+    let server = FakeAnthropic::start(vec![(200, message("42", "end_turn"))]);
+
+    provider(&server)
+        .with_thinking(Some(2000))
+        .ask(&prompt("meaning of life"))
+        .await
+        .unwrap();
+
+    let body = &server.requests()[0].body;
+    assert_eq!(body["thinking"]["type"], "enabled");
+    assert_eq!(body["thinking"]["budget_tokens"], 2000);
+    assert_eq!(body["max_tokens"], 2300);
+}
+
+#[tokio::test]
+async fn continuation_prefills_the_partial_answer() {
+    let server = FakeAnthropic::start(vec![(200, message(" in 1969.", "end_turn"))]);
+
+    let answer = provider(&server)
+        .with_thinking(Some(2000))
+        .continue_answer(&prompt("history of unix"), "It all started \n")
+        .await
+        .unwrap();
+
+    assert_eq!(answer.text, " in 1969.");
+
+    let body = &server.requests()[0].body;
+    assert_eq!(body["messages"][1]["role"], "assistant");
+    assert_eq!(body["messages"][1]["content"], "It all started");
+    assert!(body.get("thinking").is_none());
+}
+
+#[tokio::test]
+async fn continuing_an_empty_answer_asks_again_without_prefill() {
+    let server = FakeAnthropic::start(vec![(200, message("Plan 9.", "end_turn"))]);
+
+    // What's left when thinking used up max_tokens before any text
+    let answer = provider(&server)
+        .with_thinking(Some(2000))
+        .continue_answer(&prompt("history of unix"), " \n")
+        .await
+        .unwrap();
+
+    assert_eq!(answer.text, "Plan 9.");
+
+    let body = &server.requests()[0].body;
+    assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+    assert_eq!(body["messages"][0]["role"], "user");
+    assert!(body.get("thinking").is_none());
+}
+
+#[tokio::test]
+async fn http_errors_include_the_status() {
+    for (status, kind) in [(401, "authentication_error"), (429, "rate_limit_error"), (529, "overloaded_error")] {
+        let server = FakeAnthropic::start(vec![(status, api_error(kind, "nope"))]);
+
+        let error = provider(&server).ask(&prompt("hi")).await.unwrap_err().to_string();
+
+        assert!(error.contains(&status.to_string()), "{}", error);
+        assert!(error.contains(kind), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn errors_are_typed_by_status() {
+    for (status, kind, fall_back) in [
+        (401, "authentication_error", true),
+        (429, "rate_limit_error", true),
+        (529, "overloaded_error", true),
+        (400, "invalid_request_error", false),
+    ] {
+        let server = FakeAnthropic::start(vec![(status, api_error(kind, "nope"))]);
+
+        let error = provider(&server).ask(&prompt("hi")).await.unwrap_err();
+
+        let typed = ProviderError::of(&error).unwrap();
+        assert_eq!(typed.should_fall_back(), fall_back, "{}", typed);
+    }
+}
+
+#[tokio::test]
+async fn unreachable_servers_are_network_errors() {
+    // Nothing listens on port 9 (discard) on the loopback interface
+    let provider = ClaudeProvider::new("test-key".to_string(), "claude-test".to_string(), 300).with_base_url("http://127.0.0.1:9");
+
+    let error = provider.ask(&prompt("hi")).await.unwrap_err();
+
+    assert!(matches!(ProviderError::of(&error), Some(ProviderError::Network(_))), "{}", error);
+}
+
+#[tokio::test]
+async fn malformed_json_is_an_error() {
+    let server = FakeAnthropic::start(vec![(200, "{ not json".to_string())]);
+
+    assert!(provider(&server).ask(&prompt("hi")).await.is_err());
+}
+
+#[tokio::test]
+async fn missing_text_is_an_error() {
+    let server = FakeAnthropic::start(vec![(200, message_with_blocks(json!([]), "end_turn"))]);
+
+    let error = provider(&server).ask(&prompt("hi")).await.unwrap_err().to_string();
+
+    assert!(error.contains("no text"), "{}", error);
+}
+
+#[tokio::test]
+async fn earlier_turns_come_before_the_question() {
+    let server = FakeAnthropic::start(vec![(200, message("Still Rust.", "end_turn"))]);
+    let mut prompt = prompt("And now?");
+    prompt.history.push(Turn {
+        question: "What is Rust?".to_string(),
+        answer: "A language.".to_string(),
+    });
+
+    provider(&server).ask(&prompt).await.unwrap();
+
+    let messages = server.requests()[0].body["messages"].clone();
+    assert_eq!(
+        messages,
+        json!([
+            { "role": "user", "content": "What is Rust?" },
+            { "role": "assistant", "content": "A language." },
+            { "role": "user", "content": "And now?" },
+        ])
+    );
+}
+
+fn events(events: &[serde_json::Value]) -> String {
+    events
+        .iter()
+        .map(|data| format!("event: {}\ndata: {}\n\n", data["type"].as_str().unwrap(), data))
+        .collect()
+}
+
+#[tokio::test]
+async fn streams_thinking_and_text_as_they_arrive() {
+    let body = events(&[
+        json!({ "type": "message_start", "message": { "usage": { "input_tokens": 9, "output_tokens": 1 } } }),
+        json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "thinking", "thinking": "" } }),
+        json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "thinking_delta", "thinking": "Greet" } }),
+        json!({ "type": "content_block_start", "index": 1, "content_block": { "type": "text", "text": "" } }),
+        json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "text_delta", "text": "Hello" } }),
+        json!({ "type": "ping" }),
+        json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "text_delta", "text": ", world" } }),
+        json!({ "type": "message_delta", "delta": { "stop_reason": "end_turn" }, "usage": { "output_tokens": 4 } }),
+        json!({ "type": "message_stop" }),
+    ]);
+    let server = FakeAnthropic::start(vec![(200, body)]);
+
+    let pieces = std::sync::Mutex::new(Vec::new());
+    let answer = provider(&server)
+        .ask_streaming(&prompt("Hi"), &|delta: Delta<'_>| pieces.lock().unwrap().push(format!("{:?}", delta)))
+        .await
+        .unwrap();
+
+    assert_eq!(*pieces.lock().unwrap(), ["Thinking(\"Greet\")", "Text(\"Hello\")", "Text(\", world\")"]);
+    assert_eq!(answer.text, "Hello, world");
+    assert_eq!(answer.thinking.as_deref(), Some("Greet"));
+    assert_eq!(answer.stop_reason.as_deref(), Some("end_turn"));
+    assert_eq!(answer.usage.input_tokens, 9);
+    assert_eq!(answer.usage.output_tokens, 4);
+    assert_eq!(server.requests()[0].body["stream"], true);
+}
+
+#[tokio::test]
+async fn errors_mid_stream_are_typed() {
+    let body = events(&[
+        json!({ "type": "message_start", "message": { "usage": { "input_tokens": 9 } } }),
+        json!({ "type": "error", "error": { "type": "overloaded_error", "message": "Overloaded" } }),
+    ]);
+    let server = FakeAnthropic::start(vec![(200, body)]);
+
+    let error = provider(&server).ask_streaming(&prompt("Hi"), &|_: Delta<'_>| {}).await.unwrap_err();
+
+    assert!(matches!(ProviderError::of(&error), Some(ProviderError::Overloaded(_))));
+}
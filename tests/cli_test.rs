@@ -0,0 +1,766 @@
+mod common;
+
+use assert_cmd::Command;
+use common::{api_error, message, FakeAnthropic};
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+/// A config directory with the given config.toml contents.
+fn config_dir(config: &str) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("config.toml"), config).unwrap();
+    dir
+}
+
+fn mock_config() -> TempDir {
+    config_dir("provider = \"mock\"\nclaude_api_key = \"\"\nmodel = \"mock-model\"\nmax_tokens = 300\n")
+}
+
+fn claude_config(server: &FakeAnthropic) -> TempDir {
+    config_dir(&format!(
+        "claude_api_key = \"test-key\"\nclaude_base_url = \"{}\"\nmodel = \"claude-test\"\nmax_tokens = 300\n",
+        server.url
+    ))
+}
+
+fn qq(dir: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("qq").unwrap();
+    cmd.env("QQ_CONFIG_DIR", dir.path())
+        .env_remove("CLAUDE_API_KEY")
+        .env_remove("ANTHROPIC_BASE_URL")
+        .env_remove("QQ_PROVIDER")
+        .env_remove("QQ_MOCK_RESPONSE")
+        .env_remove("QQ_MOCK_SCRIPT")
+        .env_remove("QQ_COMPLETE")
+        .env_remove("FORCE_HYPERLINK")
+        .env_remove("BROWSER")
+        .env_remove("QQ_CLIPBOARD_FILE")
+        .write_stdin("");
+    cmd
+}
+
+#[test]
+fn answers_with_the_mock_provider() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .env("QQ_MOCK_RESPONSE", "Rust is a **systems** language.")
+        .arg("What is Rust?")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rust is a \x1b[1msystems\x1b[0m language."));
+}
+
+#[test]
+fn empty_input_is_not_sent() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No question provided"));
+}
+
+#[test]
+fn reads_piped_questions() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .write_stdin("capital of France?\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Mock answer to: capital of France?"));
+}
+
+#[test]
+fn json_output_and_cache_hits() {
+    let dir = mock_config();
+
+    let first = qq(&dir).args(["--json", "what is tar"]).output().unwrap();
+    let first: serde_json::Value = serde_json::from_slice(&first.stdout).unwrap();
+    assert_eq!(first["answer"], "Mock answer to: what is tar");
+    assert_eq!(first["cached"], false);
+    assert!(first["usage"]["output_tokens"].as_u64().unwrap() > 0);
+
+    let second = qq(&dir).args(["--json", "what is tar"]).output().unwrap();
+    let second: serde_json::Value = serde_json::from_slice(&second.stdout).unwrap();
+    assert_eq!(second["answer"], "Mock answer to: what is tar");
+    assert_eq!(second["cached"], true);
+
+    qq(&dir)
+        .arg("what is tar")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("cached answer"));
+}
+
+#[test]
+fn refresh_asks_again_and_replaces_the_cached_answer() {
+    let dir = mock_config();
+    let ask = |args: &[&str], reply: &str| {
+        let output = qq(&dir).env("QQ_MOCK_RESPONSE", reply).args(args).output().unwrap();
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+
+    assert_eq!(ask(&["--json", "what is tar"], "first")["answer"], "first");
+    assert_eq!(ask(&["--json", "what is tar"], "second")["answer"], "first");
+
+    let refreshed = ask(&["--json", "--refresh", "what is tar"], "second");
+    assert_eq!(refreshed["answer"], "second");
+    assert_eq!(refreshed["cached"], false);
+
+    let after = ask(&["--json", "what is tar"], "third");
+    assert_eq!(after["answer"], "second");
+    assert_eq!(after["cached"], true);
+}
+
+#[test]
+fn scripted_errors_exit_with_failure() {
+    let dir = mock_config();
+    let script = dir.path().join("script.json");
+    fs::write(&script, r#"[{ "error": "API request failed with status 529: overloaded_error" }]"#).unwrap();
+
+    qq(&dir)
+        .env("QQ_MOCK_SCRIPT", &script)
+        .arg("hello")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("overloaded"));
+}
+
+#[test]
+fn auth_failures_are_explained() {
+    let server = FakeAnthropic::start(vec![(401, api_error("authentication_error", "invalid x-api-key"))]);
+    let dir = claude_config(&server);
+
+    qq(&dir)
+        .arg("hello")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Authentication failed"));
+}
+
+#[test]
+fn rate_limits_are_explained() {
+    let server = FakeAnthropic::start(vec![(
+        429,
+        api_error("rate_limit_error", "Number of request tokens has exceeded your per-minute rate limit"),
+    )]);
+    let dir = claude_config(&server);
+
+    qq(&dir)
+        .arg("hello")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("rate limiting"));
+}
+
+#[test]
+fn overload_is_explained() {
+    let server = FakeAnthropic::start(vec![(529, api_error("overloaded_error", "Overloaded"))]);
+    let dir = claude_config(&server);
+
+    qq(&dir)
+        .arg("hello")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("overloaded"));
+}
+
+#[test]
+fn malformed_responses_fail() {
+    let server = FakeAnthropic::start(vec![(200, "<html>oops</html>".to_string())]);
+    let dir = claude_config(&server);
+
+    qq(&dir)
+        .arg("hello")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("Something went wrong"));
+}
+
+#[test]
+fn truncated_answers_are_flagged() {
+    let server = FakeAnthropic::start(vec![(200, message("Unix began", "max_tokens"))]);
+    let dir = claude_config(&server);
+
+    qq(&dir)
+        .arg("history of unix")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Unix began"))
+        .stderr(predicate::str::contains("truncated").and(predicate::str::contains("--more")));
+
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn more_continues_truncated_answers() {
+    let server = FakeAnthropic::start(vec![
+        (200, message("Unix began ", "max_tokens")),
+        (200, message(" at Bell Labs.", "end_turn")),
+    ]);
+    let dir = claude_config(&server);
+
+    qq(&dir)
+        .args(["--more", "history of unix"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Unix began").and(predicate::str::contains("at Bell Labs.")));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].body["messages"][1]["content"], "Unix began");
+}
+
+#[test]
+fn budget_refuses_from_scripts() {
+    let dir = config_dir(
+        "provider = \"mock\"\nmodel = \"mock-model\"\nmax_tokens = 300\n\n[budget]\ndaily_usd = 0.0\n",
+    );
+
+    qq(&dir)
+        .arg("hello")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("daily budget"));
+}
+
+#[test]
+fn init_prints_shell_snippets() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .args(["init", "bash"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("bind -x").and(predicate::str::contains("qq!()")));
+    qq(&dir)
+        .args(["init", "zsh"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("bindkey '^G'"));
+    qq(&dir)
+        .args(["init", "fish"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("bind \\cg"));
+}
+
+#[test]
+fn bash_snippet_is_valid_bash() {
+    let dir = mock_config();
+    let output = qq(&dir).args(["init", "bash"]).output().unwrap();
+
+    // Skip quietly on systems without bash
+    if let Ok(mut child) = std::process::Command::new("bash")
+        .arg("-n")
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+    {
+        use std::io::Write;
+        child.stdin.take().unwrap().write_all(&output.stdout).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+}
+
+#[test]
+fn completions_cover_every_shell() {
+    let dir = mock_config();
+
+    for (shell, marker) in [
+        ("bash", "complete -F _qq"),
+        ("zsh", "#compdef qq"),
+        ("fish", "complete -c qq"),
+        ("powershell", "Register-ArgumentCompleter"),
+    ] {
+        qq(&dir)
+            .args(["completions", shell])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(marker).and(predicate::str::contains("show-thinking")));
+    }
+
+    // Template names are looked up when tab is pressed
+    for shell in ["bash", "zsh", "fish"] {
+        qq(&dir)
+            .args(["completions", shell])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("QQ_COMPLETE="));
+    }
+    qq(&dir)
+        .env("QQ_COMPLETE", "models")
+        .assert()
+        .success()
+        .stdout("opus\nclaude:opus\nsonnet\nclaude:sonnet\nhaiku\nclaude:haiku\n");
+}
+
+#[test]
+fn templates_fill_piped_input() {
+    let dir = mock_config();
+    fs::create_dir(dir.path().join("templates")).unwrap();
+    fs::write(
+        dir.path().join("templates/commit.md"),
+        "---\nmodel: claude-3-5-haiku-20241022\n---\nWrite a {{style}} commit message for:\n\n{{input}}\n",
+    )
+    .unwrap();
+
+    let output = qq(&dir)
+        .args(["-t", "commit", "--var", "style=terse", "--json"])
+        .write_stdin("+fn main() {}\n")
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(json["question"], "Write a terse commit message for:\n\n+fn main() {}");
+    assert_eq!(json["model"], "claude-3-5-haiku-20241022");
+
+    qq(&dir)
+        .args(["-t", "commit", "hello"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("--var style="));
+}
+
+#[test]
+fn templates_subcommands() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .args(["templates", "new", "review"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("review.md"));
+    qq(&dir).args(["templates", "new", "review"]).assert().code(1);
+    qq(&dir)
+        .args(["templates", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("review"));
+    qq(&dir)
+        .args(["templates", "show", "review"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("{{input}}"));
+    qq(&dir).env("QQ_COMPLETE", "templates").assert().success().stdout("review\n");
+    qq(&dir)
+        .args(["-t", "nope", "hi"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("No template named 'nope'"));
+}
+
+#[test]
+fn personas_replace_or_extend_the_system_prompt() {
+    let server = FakeAnthropic::start(vec![
+        (200, message("A monad is...", "end_turn")),
+        (200, message("Arr.", "end_turn")),
+    ]);
+    let dir = claude_config(&server);
+    let config = fs::read_to_string(dir.path().join("config.toml")).unwrap();
+    fs::write(
+        dir.path().join("config.toml"),
+        config + "\n[personas.pirate]\nprompt = \"Talk like a pirate.\"\nextend = true\n",
+    )
+    .unwrap();
+
+    qq(&dir).args(["--as", "tutor", "what is a monad"]).assert().success();
+    qq(&dir).args(["--as", "pirate", "hello"]).assert().success();
+    qq(&dir)
+        .args(["--as", "nobody", "hello"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("pirate, reviewer, terse, tutor"));
+
+    let requests = server.requests();
+    let tutor = &requests[0].body;
+    assert!(tutor["system"][0]["text"].as_str().unwrap().contains("patient tutor"));
+    assert_eq!(tutor["max_tokens"], 1500);
+
+    let pirate = &requests[1].body;
+    assert!(pirate["system"][0]["text"].as_str().unwrap().contains("quick, concise answers"));
+    assert_eq!(pirate["system"][1]["text"], "Talk like a pirate.");
+    assert_eq!(pirate["max_tokens"], 300);
+}
+
+#[cfg(unix)]
+#[test]
+fn editor_questions_start_from_piped_text() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = mock_config();
+    let editor = dir.path().join("editor.sh");
+    fs::write(&editor, "#!/bin/sh\nsed -i.bak 's/^error/Why this error/' \"$1\"\n").unwrap();
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = qq(&dir)
+        .env("EDITOR", &editor)
+        .env_remove("VISUAL")
+        .args(["-e", "--json"])
+        .write_stdin("error: linker `cc` not found\n")
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(json["question"], "Why this error: linker `cc` not found");
+}
+
+#[test]
+fn cmd_prints_only_the_command() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .env("QQ_MOCK_RESPONSE", "```bash\n$ tar -czf out.tar.gz dir/\n```")
+        .args(["--cmd", "compress dir s contents"])
+        .assert()
+        .success()
+        .stdout("tar -czf out.tar.gz dir/\n");
+}
+
+#[test]
+fn why_needs_the_shell_hook() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .arg("why")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("qq init"));
+}
+
+#[test]
+fn why_explains_the_last_failure() {
+    let dir = mock_config();
+    let state = dir.path().join("cache");
+    fs::create_dir_all(&state).unwrap();
+    fs::write(state.join("last_failure"), "127\n/home/me/project\nnpm run biuld\n").unwrap();
+    fs::write(state.join("last_failure.stderr"), "npm ERR! Missing script: \"biuld\"\n").unwrap();
+
+    let output = qq(&dir).args(["why", "--json"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let question = json["question"].as_str().unwrap();
+
+    assert!(question.contains("npm run biuld"));
+    assert!(question.contains("Exit status: 127"));
+    assert!(question.contains("Missing script"));
+}
+
+#[test]
+fn why_with_more_words_is_a_question() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .args(["why", "is", "the", "sky", "blue"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Mock answer to: why is the sky blue"));
+}
+
+#[test]
+fn why_reads_piped_error_output() {
+    let dir = mock_config();
+
+    let output = qq(&dir)
+        .args(["why", "--json"])
+        .write_stdin("cc: error: no input files\n")
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert!(json["question"].as_str().unwrap().contains("no input files"));
+}
+
+#[test]
+fn links_are_numbered_and_opened() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .env("QQ_MOCK_RESPONSE", "See [the book](https://doc.rust-lang.org/book/) and [std](https://doc.rust-lang.org/std/).")
+        .arg("where do I learn Rust?")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("See the book <https://doc.rust-lang.org/book/> [1] and std <https://doc.rust-lang.org/std/> [2]."))
+        .stdout(predicate::str::contains("[2] https://doc.rust-lang.org/std/"));
+
+    qq(&dir)
+        .env("FORCE_HYPERLINK", "1")
+        .env("QQ_MOCK_RESPONSE", "See [the book](https://doc.rust-lang.org/book/).")
+        .args(["--no-cache", "where do I learn Rust?"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\x1b]8;;https://doc.rust-lang.org/book/\x1b\\the book\x1b]8;;\x1b\\"));
+
+    qq(&dir)
+        .env("BROWSER", "echo")
+        .args(["--open", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("https://doc.rust-lang.org/book/"));
+
+    qq(&dir)
+        .env("BROWSER", "echo")
+        .args(["--open", "2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("only had 1 link"));
+}
+
+#[test]
+fn answers_and_code_are_copied() {
+    let dir = mock_config();
+    let clipboard = dir.path().join("clipboard.txt");
+    let answer = "Run this:\n```bash\ntar -czf out.tgz dir\n```\nor **this**:\n```\nzip -r out.zip dir\n```";
+
+    qq(&dir)
+        .env("QQ_MOCK_RESPONSE", answer)
+        .env("QQ_CLIPBOARD_FILE", &clipboard)
+        .args(["--copy", "how do I archive a dir?"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Copied the answer"));
+    assert_eq!(fs::read_to_string(&clipboard).unwrap(), "Run this:\nbash\ntar -czf out.tgz dir\n\nor this:\n\nzip -r out.zip dir");
+
+    qq(&dir)
+        .env("QQ_MOCK_RESPONSE", answer)
+        .env("QQ_CLIPBOARD_FILE", &clipboard)
+        .args(["--copy-code=2", "how do I archive a dir?"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Copied the code block 2"));
+    assert_eq!(fs::read_to_string(&clipboard).unwrap(), "zip -r out.zip dir");
+
+    qq(&dir)
+        .env("QQ_MOCK_RESPONSE", answer)
+        .env("QQ_CLIPBOARD_FILE", &clipboard)
+        .args(["--copy-code=3", "how do I archive a dir?"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("only has 2 code block(s)"));
+}
+
+#[test]
+fn unreadable_images_stop_before_asking() {
+    let dir = mock_config();
+    let notes = dir.path().join("notes.png");
+    fs::write(&notes, "not an image").unwrap();
+
+    qq(&dir)
+        .arg("-i")
+        .arg(&notes)
+        .arg("what's in it?")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("isn't a PNG, JPEG, GIF or WebP image"));
+}
+
+#[test]
+fn documents_are_attached_as_text() {
+    let server = FakeAnthropic::start(vec![(200, message("It's about install steps.", "end_turn"))]);
+    let dir = claude_config(&server);
+    let page = dir.path().join("install.html");
+    fs::write(&page, "<h1>Install</h1><p>Run make.</p>").unwrap();
+
+    qq(&dir)
+        .arg("-f")
+        .arg(&page)
+        .args(["--no-cache", "what is this about?"])
+        .assert()
+        .success();
+
+    let content = &server.requests()[0].body["messages"][0]["content"];
+    assert_eq!(content[0]["title"], "install.html");
+    assert_eq!(content[0]["source"]["data"], "Install\nRun make.");
+
+    qq(&dir)
+        .arg("-f")
+        .arg(format!("{}:2", page.display()))
+        .arg("what is this about?")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Page ranges only work for PDFs"));
+}
+
+#[test]
+fn batches_keep_their_order_and_resume() {
+    let dir = mock_config();
+    let questions = dir.path().join("questions.txt");
+    let answers = dir.path().join("questions.answers.jsonl");
+    fs::write(&questions, "one\ntwo\n{\"question\": \"three\", \"as\": \"nobody\"}\nfour\n").unwrap();
+
+    qq(&dir)
+        .args(["batch", "-j", "3"])
+        .arg(&questions)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("3 answered"))
+        .stderr(predicate::str::contains("1 failed"));
+    let lines: Vec<serde_json::Value> = fs::read_to_string(&answers)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let questions_in_order: Vec<&str> = lines.iter().map(|line| line["question"].as_str().unwrap()).collect();
+    assert_eq!(questions_in_order, ["one", "two", "three", "four"]);
+    assert_eq!(lines[1]["answer"], "Mock answer to: two");
+    assert_eq!(lines[2]["error"], "No persona named 'nobody'");
+
+    fs::write(&questions, "one\ntwo\n{\"question\": \"three\", \"as\": \"terse\"}\nfour\n").unwrap();
+    let report = dir.path().join("report.md");
+    fs::copy(&answers, dir.path().join("report.progress.jsonl")).unwrap();
+
+    qq(&dir)
+        .arg("batch")
+        .arg(&questions)
+        .arg("-o")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 answered by an earlier run").and(predicate::str::contains("✅ 1 answered")));
+    assert_eq!(
+        fs::read_to_string(&report).unwrap(),
+        "## one\n\nMock answer to: one\n\n## two\n\nMock answer to: two\n\n\
+         ## three\n\nMock answer to: three\n\n## four\n\nMock answer to: four\n\n"
+    );
+    assert!(!dir.path().join("report.progress.jsonl").exists());
+}
+
+#[test]
+fn batches_leave_the_questions_and_other_files_alone() {
+    let dir = mock_config();
+    let questions = dir.path().join("questions.txt");
+    fs::write(&questions, "one\n").unwrap();
+
+    qq(&dir)
+        .arg("batch")
+        .arg(&questions)
+        .arg("-o")
+        .arg(dir.path().join(".").join("questions.txt"))
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("is the file of questions"));
+    assert_eq!(fs::read_to_string(&questions).unwrap(), "one\n");
+
+    let neighbour = dir.path().join("report.tmp");
+    fs::write(&neighbour, "mine").unwrap();
+    qq(&dir).arg("batch").arg(&questions).arg("-o").arg(dir.path().join("report.md")).assert().success();
+    assert_eq!(fs::read_to_string(&neighbour).unwrap(), "mine");
+}
+
+#[test]
+fn batches_count_running_requests_against_the_budget() {
+    // Room for one request at its worst case (300 tokens at $15/Mtok), not two
+    let dir = config_dir(
+        "provider = \"mock\"\nclaude_api_key = \"\"\nmodel = \"mock-model\"\nmax_tokens = 300\n\n[budget]\ndaily_usd = 0.006\n",
+    );
+    let questions = dir.path().join("questions.txt");
+    fs::write(&questions, "one\ntwo\nthree\nfour\n").unwrap();
+
+    qq(&dir)
+        .args(["batch", "-j", "4"])
+        .arg(&questions)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("✅ 1 answered"))
+        .stderr(predicate::str::contains("counting requests still running"));
+}
+
+#[test]
+fn comparisons_answer_in_order_and_are_recorded() {
+    let dir = mock_config();
+
+    let output = qq(&dir)
+        .args(["--json", "--compare", "mock:first,mock", "What is Rust?"])
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["question"], "What is Rust?");
+    let models: Vec<&str> = json["results"].as_array().unwrap().iter().map(|result| result["model"].as_str().unwrap()).collect();
+    assert_eq!(models, ["first", "mock-model"]);
+    assert_eq!(json["results"][1]["answer"], "Mock answer to: What is Rust?");
+
+    let saved: Vec<_> = fs::read_dir(dir.path().join("cache").join("comparisons")).unwrap().collect();
+    assert_eq!(saved.len(), 1);
+    let record: serde_json::Value = serde_json::from_slice(&fs::read(saved[0].as_ref().unwrap().path()).unwrap()).unwrap();
+    assert_eq!(record, json);
+}
+
+#[test]
+fn comparisons_show_each_answer_with_its_stats() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .args(["--compare", "mock:first,mock:second", "What is Rust?"])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("── mock:first ──")
+                .and(predicate::str::contains("── mock:second ──"))
+                .and(predicate::str::contains(" in · ")),
+        );
+}
+
+#[test]
+fn outages_fall_back_to_the_next_provider() {
+    let server = FakeAnthropic::start(vec![(529, api_error("overloaded_error", "Overloaded"))]);
+    let dir = config_dir(&format!(
+        "claude_api_key = \"test-key\"\nclaude_base_url = \"{}\"\nmodel = \"claude-test\"\nmax_tokens = 300\nfallback = [\"mock:backup\"]\n",
+        server.url
+    ));
+
+    let output = qq(&dir).args(["--json", "What is Rust?"]).output().unwrap();
+
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["answer"], "Mock answer to: What is Rust?");
+    assert_eq!(json["model"], "backup");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("claude:claude-test is unavailable, answered by mock:backup"));
+}
+
+#[test]
+fn unknown_fallback_providers_are_skipped() {
+    let dir = config_dir(
+        "provider = \"mock\"\nclaude_api_key = \"\"\nmodel = \"mock-model\"\nmax_tokens = 300\nfallback = [\"claude:sonnet\", \"openai:gpt-4o-mini\", \"ollama:llama3\"]\n",
+    );
+
+    qq(&dir)
+        .arg("hello")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Mock answer to: hello"))
+        .stderr(
+            predicate::str::contains("Skipping fallback 'openai:gpt-4o-mini': Unknown provider 'openai'")
+                .and(predicate::str::contains("Skipping fallback 'ollama:llama3'")),
+        );
+}
+
+#[test]
+fn rejected_requests_do_not_fall_back() {
+    let server = FakeAnthropic::start(vec![(400, api_error("invalid_request_error", "prompt is too long"))]);
+    let dir = config_dir(&format!(
+        "claude_api_key = \"test-key\"\nclaude_base_url = \"{}\"\nmodel = \"claude-test\"\nmax_tokens = 300\nfallback = [\"mock:backup\"]\n",
+        server.url
+    ));
+
+    qq(&dir)
+        .arg("What is Rust?")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("Mock answer").not())
+        .stderr(predicate::str::contains("prompt is too long"));
+}
+
+#[test]
+fn serving_beyond_localhost_needs_a_token() {
+    let dir = mock_config();
+
+    qq(&dir)
+        .env_remove("QQ_SERVE_TOKEN")
+        .args(["serve", "--host", "0.0.0.0", "--port", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Add --token"));
+}
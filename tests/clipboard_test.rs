@@ -0,0 +1,24 @@
+use quick_question::clipboard::code_blocks;
+use quick_question::render::plain_text;
+
+#[test]
+fn code_blocks_are_found_without_their_fences() {
+    let answer = "First:\n```bash\ncd /tmp\nls\n```\nThen:\n```\nrm -r x\n```";
+
+    assert_eq!(code_blocks(answer), ["cd /tmp\nls", "rm -r x"]);
+}
+
+#[test]
+fn inline_code_is_used_without_blocks() {
+    let answer = "Use `tar -czf out.tgz dir` or `zip -r out.zip dir`.";
+
+    assert_eq!(code_blocks(answer), ["tar -czf out.tgz dir", "zip -r out.zip dir"]);
+    assert!(code_blocks("No code here.").is_empty());
+}
+
+#[test]
+fn plain_text_drops_markdown_and_escapes() {
+    let answer = "Use **`ls -la`**, see [the manual](https://man.example/ls).";
+
+    assert_eq!(plain_text(answer), "Use ls -la, see the manual <https://man.example/ls>.");
+}
@@ -0,0 +1,54 @@
+use quick_question::compare::{panes, wrap, Outcome};
+use quick_question::providers::{Answer, Target, Usage};
+use std::time::Duration;
+
+fn target(provider: &str, model: &str) -> Target {
+    Target {
+        provider: provider.to_string(),
+        model: model.to_string(),
+    }
+}
+
+#[test]
+fn wrapping_keeps_indentation_and_splits_long_words() {
+    assert_eq!(wrap("the quick brown fox", 10), ["the quick", "brown fox"]);
+    assert_eq!(wrap("  let x = 1;", 8), ["  let x", "  = 1;"]);
+    assert_eq!(wrap("abcdefghij k", 4), ["abcd", "efgh", "ij k"]);
+    assert_eq!(wrap("one\n\ntwo", 10), ["one", "", "two"]);
+}
+
+#[test]
+fn panes_line_up_side_by_side() {
+    let answered = Outcome {
+        target: target("mock", "a"),
+        answer: Ok(Answer {
+            text: "A **short** answer here".to_string(),
+            thinking: None,
+            stop_reason: Some("end_turn".to_string()),
+            usage: Usage {
+                input_tokens: 10,
+                output_tokens: 5,
+                ..Usage::default()
+            },
+        }),
+        latency: Duration::from_millis(1200),
+    };
+    let failed = Outcome {
+        target: target("mock", "b"),
+        answer: Err("overloaded".to_string()),
+        latency: Duration::from_millis(300),
+    };
+
+    let lines = panes(&[answered, failed], 43);
+
+    assert_eq!(
+        lines,
+        [
+            "mock:a               │ mock:b",
+            "──────────────────── │ ────────────────────",
+            "A short answer here  │ Failed: overloaded",
+            "──────────────────── │ ────────────────────",
+            "1.2s · 10 in · 5 ou… │ failed after 0.3s",
+        ]
+    );
+}
@@ -0,0 +1,77 @@
+use quick_question::document::{docx_xml_text, html_text, parse_spec, Content, Document, PageRange};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn range(first: u32, last: Option<u32>) -> Option<PageRange> {
+    Some(PageRange { first, last })
+}
+
+#[test]
+fn page_ranges() {
+    let cases = [
+        ("3", range(3, Some(3))),
+        ("3-7", range(3, Some(7))),
+        ("3-", range(3, None)),
+        ("0-2", None),
+        ("7-3", None),
+        ("draft", None),
+        ("", None),
+    ];
+    for (text, expected) in cases {
+        assert_eq!(PageRange::parse(text), expected, "{:?}", text);
+    }
+}
+
+#[test]
+fn specs_split_off_a_trailing_range_only() {
+    let cases = [
+        ("spec.pdf:3-7", "spec.pdf", range(3, Some(7))),
+        ("spec.pdf", "spec.pdf", None),
+        ("notes:draft.txt", "notes:draft.txt", None),
+        ("C:\\docs\\spec.pdf:2", "C:\\docs\\spec.pdf", range(2, Some(2))),
+    ];
+    for (spec, path, pages) in cases {
+        assert_eq!(parse_spec(spec), (PathBuf::from(path), pages), "{:?}", spec);
+    }
+}
+
+#[test]
+fn html_pages_become_readable_text() {
+    let html = "<!DOCTYPE html><html><head><title>T</title><style>p { color: red }</style>\n<script>alert('<p>')</script></head>\n<body><h1>Install</h1>\n  <p>Run <code>make</code> &amp; wait&nbsp;a bit.</p><ul><li>One</li><li>Two &#x2192; three</li></ul></body></html>";
+
+    assert_eq!(html_text(html), "T\nInstall\nRun make & wait a bit.\nOne\nTwo → three");
+}
+
+#[test]
+fn word_xml_keeps_paragraphs_and_tabs() {
+    let xml = r#"<w:document><w:body><w:p><w:r><w:t>Hello</w:t></w:r><w:r><w:t xml:space="preserve"> world</w:t></w:r></w:p><w:p><w:r><w:t>a</w:t><w:tab/><w:t>b &lt; c</w:t></w:r></w:p></w:body></w:document>"#;
+
+    assert_eq!(docx_xml_text(xml), "Hello world\na\tb < c");
+}
+
+#[test]
+fn pdfs_are_kept_whole_for_providers_that_read_them() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("spec.pdf");
+    let pdf = b"%PDF-1.4\n1 0 obj << /Type /Pages /Count 2 >>\n2 0 obj << /Type /Page >>\n3 0 obj << /Type/Page >>\n%%EOF";
+    std::fs::write(&path, pdf).unwrap();
+
+    let document = Document::load(path.to_str().unwrap(), true).unwrap();
+
+    assert_eq!(document.title(), "spec.pdf");
+    assert!(matches!(&document.content, Content::Pdf(data) if data == pdf));
+    assert_eq!(document.estimated_tokens(), 5000);
+}
+
+#[test]
+fn text_files_load_and_page_ranges_need_a_pdf() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("page.html");
+    std::fs::write(&path, "<p>Hi &amp; bye</p>").unwrap();
+
+    let document = Document::load(path.to_str().unwrap(), true).unwrap();
+    assert!(matches!(&document.content, Content::Text(text) if text == "Hi & bye"));
+
+    let error = Document::load(&format!("{}:2-3", path.display()), true).unwrap_err();
+    assert_eq!(error.to_string(), "Page ranges only work for PDFs, not page.html");
+}
@@ -0,0 +1,109 @@
+use quick_question::config::Config;
+use quick_question::prompt::Prompt;
+use quick_question::providers::fallback::FallbackProvider;
+use quick_question::providers::mock::{MockProvider, MockReply};
+use quick_question::providers::{Provider, Target};
+
+fn target(provider: &str, model: &str) -> Target {
+    Target {
+        provider: provider.to_string(),
+        model: model.to_string(),
+    }
+}
+
+fn reply(text: &str) -> MockReply {
+    MockReply {
+        text: text.to_string(),
+        ..MockReply::default()
+    }
+}
+
+fn failure(error: &str) -> MockReply {
+    MockReply {
+        error: Some(error.to_string()),
+        ..MockReply::default()
+    }
+}
+
+fn chain(replies: Vec<Vec<MockReply>>) -> FallbackProvider {
+    FallbackProvider::new(
+        replies
+            .into_iter()
+            .enumerate()
+            .map(|(index, replies)| {
+                let provider: Box<dyn Provider> = Box::new(MockProvider::new(replies));
+                (target("mock", &format!("m{}", index)), provider)
+            })
+            .collect(),
+    )
+}
+
+fn prompt() -> Prompt {
+    Prompt::with_system(Vec::new(), "hi")
+}
+
+#[test]
+fn targets_name_a_provider_a_model_or_both() {
+    let config = Config {
+        model: "claude-default".to_string(),
+        ..Config::default()
+    };
+
+    assert_eq!(Target::parse("mock:fast", &config).unwrap(), target("mock", "fast"));
+    assert_eq!(Target::parse(" mock ", &config).unwrap(), target("mock", "claude-default"));
+    assert_eq!(Target::parse("claude-haiku-4-5", &config).unwrap(), target("claude", "claude-haiku-4-5"));
+    assert_eq!(Target::parse("claude:sonnet", &config).unwrap(), target("claude", "claude-sonnet-4-5"));
+    assert_eq!(Target::parse("Haiku", &config).unwrap(), target("claude", "claude-haiku-4-5"));
+    assert_eq!(Target::parse("mock:sonnet", &config).unwrap(), target("mock", "sonnet"));
+    assert_eq!(
+        Target::parse("nope:x", &config).unwrap_err().to_string(),
+        "Unknown provider 'nope' (expected claude or mock)"
+    );
+}
+
+#[tokio::test]
+async fn the_primary_answers_when_it_can() {
+    let provider = chain(vec![vec![reply("first")], vec![reply("second")]]);
+
+    assert_eq!(provider.ask(&prompt()).await.unwrap().text, "first");
+    assert_eq!(provider.fell_back_to(), None);
+}
+
+#[tokio::test]
+async fn outages_move_down_the_chain() {
+    let provider = chain(vec![
+        vec![failure("API request failed with status 529: overloaded_error")],
+        vec![failure("API request failed with status 401: authentication_error")],
+        vec![reply("third"), reply(" and more")],
+    ]);
+
+    assert_eq!(provider.ask(&prompt()).await.unwrap().text, "third");
+    assert_eq!(provider.fell_back_to(), Some(target("mock", "m2")));
+
+    // The rest of an answer comes from whoever started it
+    assert_eq!(provider.continue_answer(&prompt(), "third").await.unwrap().text, " and more");
+}
+
+#[tokio::test]
+async fn rejected_requests_do_not_fall_back() {
+    let provider = chain(vec![
+        vec![failure("API request failed with status 400: prompt is too long")],
+        vec![reply("second")],
+    ]);
+
+    let error = provider.ask(&prompt()).await.unwrap_err();
+
+    assert!(error.to_string().contains("prompt is too long"), "{}", error);
+}
+
+#[tokio::test]
+async fn the_last_outage_is_reported_when_all_fail() {
+    let provider = chain(vec![
+        vec![failure("API request failed with status 529: overloaded_error")],
+        vec![failure("API request failed with status 429: rate_limit_error")],
+    ]);
+
+    let error = provider.ask(&prompt()).await.unwrap_err();
+
+    assert!(error.to_string().contains("rate_limit_error"), "{}", error);
+}
@@ -1,115 +1,101 @@
-use std::io::{self, Write};
-
-// Copy the formatting functions here for testing
-fn format_for_terminal(text: &str) -> String {
-    let mut result = text.to_string();
-    
-    // Handle code blocks - remove ``` markers
-    result = result.replace("```", "");
-    
-    // Convert Markdown bold to ANSI bold (**text**)
-    result = replace_markdown_pattern(&result, "**", "\x1b[1m", "\x1b[0m");
-    
-    // Convert Markdown code spans to highlighted text (`code`)
-    result = replace_markdown_pattern(&result, "`", "\x1b[93m", "\x1b[0m");
-    
-    // Convert strikethrough (~~text~~)
-    result = replace_markdown_pattern(&result, "~~", "\x1b[9m", "\x1b[0m");
-    
-    // Convert Markdown italic to ANSI italic (*text*) - do this last to avoid conflicts
-    result = replace_single_asterisk_italic(&result);
-    
-    result
+use quick_question::config::{Config, TypingMode};
+use quick_question::render::{
+    format_for_terminal, replace_markdown_pattern, replace_single_asterisk_italic, typing_plan, Typing,
+};
+use std::time::Duration;
+
+#[test]
+fn formats_all_supported_markdown() {
+    let input = "Here's a test: **bold text**, *italic text*, `code block`, and ~~strikethrough~~!";
+    let expected = "Here's a test: \x1b[1mbold text\x1b[0m, \x1b[3mitalic text\x1b[0m, \x1b[93mcode block\x1b[0m, and \x1b[9mstrikethrough\x1b[0m!";
+
+    assert_eq!(format_for_terminal(input), expected);
+}
+
+#[test]
+fn strips_code_fences() {
+    let input = "```\nls -la\n```";
+
+    assert_eq!(format_for_terminal(input), "\nls -la\n");
+}
+
+#[test]
+fn leaves_plain_text_alone() {
+    let input = "Nothing to see here. 🚀 ┌──┐";
+
+    assert_eq!(format_for_terminal(input), input);
+}
+
+#[test]
+fn unclosed_markers_are_kept() {
+    assert_eq!(replace_markdown_pattern("a **b", "**", "<", ">"), "a **b");
+    assert_eq!(replace_single_asterisk_italic("5 * 3"), "5 * 3");
+}
+
+#[test]
+fn multiple_spans_on_one_line() {
+    let output = replace_markdown_pattern("`a` and `b`", "`", "<", ">");
+
+    assert_eq!(output, "<a> and <b>");
 }
 
-fn replace_markdown_pattern(text: &str, marker: &str, start_ansi: &str, end_ansi: &str) -> String {
-    let mut result = String::new();
-    let chars = text.chars().collect::<Vec<_>>();
-    let mut i = 0;
-    
-    while i < chars.len() {
-        if i + marker.len() <= chars.len() && 
-           chars[i..i + marker.len()].iter().collect::<String>() == marker {
-            // Found opening marker, look for closing marker
-            let mut j = i + marker.len();
-            while j + marker.len() <= chars.len() {
-                if chars[j..j + marker.len()].iter().collect::<String>() == marker {
-                    // Found closing marker
-                    let content: String = chars[i + marker.len()..j].iter().collect();
-                    result.push_str(start_ansi);
-                    result.push_str(&content);
-                    result.push_str(end_ansi);
-                    i = j + marker.len();
-                    break;
-                }
-                j += 1;
-            }
-            if j + marker.len() > chars.len() {
-                // No closing marker found, just add the character
-                result.push(chars[i]);
-                i += 1;
-            }
-        } else {
-            result.push(chars[i]);
-            i += 1;
-        }
-    }
-    
-    result
+#[test]
+fn italic_ignores_double_asterisks() {
+    assert_eq!(replace_single_asterisk_italic("**x**"), "**x**");
+    assert_eq!(replace_single_asterisk_italic("*x* **y**"), "\x1b[3mx\x1b[0m **y**");
 }
 
-fn replace_single_asterisk_italic(text: &str) -> String {
-    let mut result = String::new();
-    let chars: Vec<char> = text.chars().collect();
-    let mut i = 0;
-    
-    while i < chars.len() {
-        if chars[i] == '*' {
-            // Check if it's not part of ** (already processed)
-            let prev_is_asterisk = i > 0 && chars[i - 1] == '*';
-            let next_is_asterisk = i + 1 < chars.len() && chars[i + 1] == '*';
-            
-            if !prev_is_asterisk && !next_is_asterisk {
-                // Look for closing single asterisk
-                let mut j = i + 1;
-                while j < chars.len() {
-                    if chars[j] == '*' && (j + 1 >= chars.len() || chars[j + 1] != '*') {
-                        // Found closing single asterisk
-                        let content: String = chars[i + 1..j].iter().collect();
-                        result.push_str("\x1b[3m");
-                        result.push_str(&content);
-                        result.push_str("\x1b[0m");
-                        i = j + 1;
-                        break;
-                    }
-                    j += 1;
-                }
-                if j >= chars.len() {
-                    // No closing found
-                    result.push(chars[i]);
-                    i += 1;
-                }
-            } else {
-                result.push(chars[i]);
-                i += 1;
-            }
-        } else {
-            result.push(chars[i]);
-            i += 1;
-        }
-    }
-    
-    result
+fn typing(mode: TypingMode, max_secs: u64) -> Typing {
+    Typing { mode, max: Duration::from_secs(max_secs) }
 }
 
-fn main() {
-    let test_input = "Here's a test: **bold text**, *italic text*, `code block`, and ~~strikethrough~~!";
-    
-    println!("🧪 Formatting Test");
-    println!("==================");
-    println!();
-    println!("Input:  {}", test_input);
-    println!("Output: {}", format_for_terminal(test_input));
-    println!();
-    println!("✅ If you see different formatting above, it's working!");
+#[test]
+fn odd_typing_caps_do_not_panic() {
+    let cap = |secs: f64| {
+        let config = Config { typing_max_secs: secs, ..Config::default() };
+        Typing::from_config(&config).max
+    };
+
+    assert_eq!(cap(1.5), Duration::from_millis(1500));
+    assert_eq!(cap(-3.0), Duration::ZERO);
+    assert_eq!(cap(f64::INFINITY), Duration::from_secs(60));
+    assert_eq!(cap(1e20), Duration::from_secs(60));
+    assert_eq!(cap(f64::NAN), Duration::from_secs(2));
+}
+
+#[test]
+fn natural_typing_pauses_at_sentence_ends_only() {
+    let plan = typing_plan("Pi is 3.14. Done", typing(TypingMode::Natural, 60));
+    let delay_of = |index: usize| plan[index].1;
+
+    // The decimal point doesn't pause, the full stop after it does
+    assert_eq!(plan[7].0, ".");
+    assert_eq!(delay_of(7), Duration::from_millis(8));
+    assert_eq!(plan[10].0, ".");
+    assert_eq!(delay_of(10), Duration::from_millis(150));
+}
+
+#[test]
+fn typing_keeps_escape_sequences_whole() {
+    let plan = typing_plan("a \x1b[1mb\x1b[0m", typing(TypingMode::Fast, 60));
+    let chunks: Vec<&str> = plan.iter().map(|(chunk, _)| *chunk).collect();
+
+    assert_eq!(chunks, ["a", " ", "\x1b[1m", "b", "\x1b[0m"]);
+    assert_eq!(plan[2].1, Duration::ZERO);
+
+    let plan = typing_plan("\x1b]8;;https://a.example\x1b\\x\x1b]8;;\x1b\\", typing(TypingMode::Fast, 60));
+    let chunks: Vec<&str> = plan.iter().map(|(chunk, _)| *chunk).collect();
+
+    assert_eq!(chunks, ["\x1b]8;;https://a.example\x1b\\", "x", "\x1b]8;;\x1b\\"]);
+}
+
+#[test]
+fn long_answers_are_typed_within_the_cap() {
+    let answer = "A sentence. ".repeat(500);
+    let plan = typing_plan(&answer, typing(TypingMode::Natural, 2));
+    let total: Duration = plan.iter().map(|(_, delay)| *delay).sum();
+
+    assert_eq!(plan.iter().map(|(chunk, _)| *chunk).collect::<String>(), answer);
+    assert!(total <= Duration::from_secs(2), "took {:?}", total);
+    assert!(total > Duration::from_secs(1), "took {:?}", total);
 }
@@ -0,0 +1,77 @@
+use quick_question::image::Image;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    data.extend(width.to_be_bytes());
+    data.extend(height.to_be_bytes());
+    data
+}
+
+#[test]
+fn formats_and_sizes_come_from_the_headers() {
+    let jpeg = [
+        &[0xFF, 0xD8][..],
+        &[0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00],             // APP0, skipped
+        &[0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x02, 0x58, 0x03, 0x20], // SOF0: 600 high, 800 wide
+    ]
+    .concat();
+    let gif = b"GIF89a\x40\x01\xf0\x00".to_vec();
+    let webp_lossy = [&b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0\x9d\x01\x2a"[..], &[0x00, 0x04, 0x00, 0x03]].concat();
+    let webp_lossless = [&b"RIFF\0\0\0\0WEBPVP8L\0\0\0\0\x2f"[..], &[0x3F, 0xC0, 0x1F, 0x00]].concat();
+    let webp_extended = [&b"RIFF\0\0\0\0WEBPVP8X\0\0\0\0\0\0\0\0"[..], &[0x7F, 0x07, 0x00, 0x37, 0x04, 0x00]].concat();
+
+    let cases: [(Vec<u8>, &str, (u32, u32)); 6] = [
+        (png(2560, 1440), "image/png", (2560, 1440)),
+        (jpeg, "image/jpeg", (800, 600)),
+        (gif, "image/gif", (320, 240)),
+        (webp_lossy, "image/webp", (1024, 768)),
+        (webp_lossless, "image/webp", (64, 128)),
+        (webp_extended, "image/webp", (1920, 1080)),
+    ];
+    for (data, media_type, size) in cases {
+        let image = Image::from_bytes(Path::new("shot"), data).unwrap();
+        assert_eq!(image.media_type, media_type);
+        assert_eq!((image.width, image.height), size, "{}", media_type);
+    }
+}
+
+#[test]
+fn other_files_are_rejected() {
+    let error = Image::from_bytes(Path::new("notes.png"), b"just text".to_vec()).unwrap_err();
+
+    assert!(error.to_string().contains("notes.png isn't a PNG, JPEG, GIF or WebP image"));
+}
+
+#[test]
+fn small_images_load_unchanged() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("small.png");
+    std::fs::write(&path, png(640, 480)).unwrap();
+
+    let image = Image::load(&path, 1568).unwrap();
+
+    assert_eq!(image.data, png(640, 480));
+    assert_eq!(image.estimated_tokens(), 410);
+}
+
+#[test]
+fn images_over_the_hard_limit_need_scaling() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("huge.png");
+    std::fs::write(&path, png(10000, 100)).unwrap();
+
+    // Not a real PNG, so no tool can shrink it either
+    let error = Image::load(&path, 0).unwrap_err();
+
+    assert!(error.to_string().contains("too large to send (10000x100"), "{}", error);
+}
+
+#[test]
+fn token_estimates_assume_the_api_scales_large_images() {
+    let image = Image::from_bytes(Path::new("4k.png"), png(3840, 2160)).unwrap();
+
+    // Scaled to 1568x882 before counting
+    assert_eq!(image.estimated_tokens(), 1844);
+}
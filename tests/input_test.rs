@@ -0,0 +1,67 @@
+use quick_question::input::{ctrl_d_sends, is_complete, read_until_blank_line, strip_instructions, SlashCommand};
+
+#[test]
+fn interactive_input_ends_at_a_blank_line() {
+    let typed = "\nwhy does this fail?\n  let x: u8 = 256;\n\nnot part of it\n";
+
+    let question = read_until_blank_line(typed.as_bytes()).unwrap();
+
+    assert_eq!(question, "why does this fail?\n  let x: u8 = 256;");
+}
+
+#[test]
+fn interactive_input_ends_at_eof() {
+    assert_eq!(read_until_blank_line("one\ntwo".as_bytes()).unwrap(), "one\ntwo");
+    assert_eq!(read_until_blank_line("".as_bytes()).unwrap(), "");
+}
+
+#[test]
+fn editor_instructions_are_cut_off() {
+    let edited = "What does this do?\n\n#!/bin/sh\n# count lines\nwc -l\n\n\n\
+                  # ------------------------ >8 ------------------------\n\
+                  # Write your question above the line, then save and quit to send it.\n\
+                  # stray text below the line\n";
+
+    assert_eq!(strip_instructions(edited), "What does this do?\n\n#!/bin/sh\n# count lines\nwc -l");
+}
+
+#[test]
+fn editor_instructions_are_dropped_without_the_marker() {
+    let edited = "# a yaml comment\nkey: value\n\
+                  # Write your question above the line, then save and quit to send it.\n\
+                  # The line and everything below it are left out. Leave it empty to cancel.\n";
+
+    assert_eq!(strip_instructions(edited), "# a yaml comment\nkey: value");
+}
+
+#[test]
+fn enter_sends_empty_input_commands_and_finished_paragraphs() {
+    assert!(is_complete(""));
+    assert!(is_complete("   "));
+    assert!(is_complete("why does this fail?\n"));
+    assert!(is_complete("/help"));
+    assert!(is_complete("/quit now"));
+
+    assert!(!is_complete("why does this fail?"));
+    assert!(!is_complete("first line\n  let x: u8 = 256;"));
+}
+
+#[test]
+fn ctrl_d_sends_unless_there_is_nothing_to_send() {
+    assert!(ctrl_d_sends("first line\n  let x: u8 = 256;"));
+    assert!(ctrl_d_sends("why?"));
+
+    assert!(!ctrl_d_sends(""));
+}
+
+#[test]
+fn slash_commands_must_match_exactly() {
+    assert_eq!(SlashCommand::parse("/edit"), Some(SlashCommand::Edit));
+    assert_eq!(SlashCommand::parse("/help"), Some(SlashCommand::Help));
+    assert_eq!(SlashCommand::parse(" /quit "), Some(SlashCommand::Quit));
+
+    assert_eq!(SlashCommand::parse("/Quit"), None);
+    assert_eq!(SlashCommand::parse("/edits"), None);
+    assert_eq!(SlashCommand::parse("/usr/bin/env is what?"), None);
+    assert_eq!(SlashCommand::parse("help"), None);
+}
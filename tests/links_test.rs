@@ -0,0 +1,54 @@
+use quick_question::links::{find, open, render};
+
+#[test]
+fn links_are_found_in_order_without_repeats() {
+    let text = "Read [the book](https://a.example/book), then [std](https://a.example/std) and [the book again](https://a.example/book).";
+
+    assert_eq!(find(text), ["https://a.example/book", "https://a.example/std"]);
+}
+
+#[test]
+fn urls_keep_their_own_parentheses() {
+    let text = "See [Rust](https://en.wikipedia.org/wiki/Rust_(programming_language)).";
+
+    assert_eq!(find(text), ["https://en.wikipedia.org/wiki/Rust_(programming_language)"]);
+}
+
+#[test]
+fn things_that_only_look_like_links_are_left_alone() {
+    for text in ["arr[i](x y)", "[]()", "use `v[0]` (the first)", "[a\nb](https://a.example)"] {
+        assert!(find(text).is_empty(), "{:?}", text);
+        assert_eq!(render(text, &mut Vec::new(), true), text);
+    }
+}
+
+#[test]
+fn links_render_as_text_url_and_number_without_hyperlinks() {
+    let mut urls = Vec::new();
+
+    let rendered = render("Try [docs](https://a.example) now.", &mut urls, false);
+
+    assert_eq!(rendered, "Try docs <https://a.example> [1] now.");
+    assert_eq!(urls, ["https://a.example"]);
+}
+
+#[test]
+fn hyperlinks_are_numbered_after_earlier_links() {
+    let mut urls = vec!["https://first.example".to_string()];
+
+    let rendered = render("[again](https://first.example) [new](https://second.example)", &mut urls, true);
+
+    assert_eq!(
+        rendered,
+        "\x1b]8;;https://first.example\x1b\\again\x1b]8;;\x1b\\\x1b[2m[1]\x1b[0m \x1b]8;;https://second.example\x1b\\new\x1b]8;;\x1b\\\x1b[2m[2]\x1b[0m"
+    );
+    assert_eq!(urls, ["https://first.example", "https://second.example"]);
+}
+
+#[test]
+fn only_web_links_are_opened() {
+    for url in ["-x", "--help", "file:///etc/passwd", "javascript:alert(1)"] {
+        let error = open(url).unwrap_err().to_string();
+        assert!(error.contains("only http and https"), "{}: {}", url, error);
+    }
+}
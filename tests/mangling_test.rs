@@ -0,0 +1,41 @@
+use quick_question::mangling::detect;
+
+/// Arguments as the shell handed them over, and the question qq should
+/// suggest instead (None when they look fine).
+const CASES: &[(&[&str], Option<&str>)] = &[
+    // Quote pairs glue the words between two contractions into one argument
+    (&["whats up, its", "late"], Some("what's up, it's late")),
+    (&["dont know why its", "slow"], Some("don't know why it's slow")),
+    (&["why", "doesnt my code work, isnt", "it", "valid?"], Some("why doesn't my code work, isn't it valid?")),
+    // Apostrophe-less contractions that aren't words of their own
+    (&["whats", "the", "time"], Some("what's the time")),
+    (&["Whats", "a", "monad?"], Some("What's a monad?")),
+    (&["im", "stuck"], Some("I'm stuck")),
+    (&["why", "cant", "I", "push?"], Some("why can't I push?")),
+    // Endings that came through as words of their own
+    (&["what", "s", "the", "time"], Some("what's the time")),
+    (&["I", "don", "t", "know"], Some("I don't know")),
+    (&["you", "re", "right"], Some("you're right")),
+    (&["is", "it", "s", "fine?"], Some("is it's fine?")),
+    (&["where", "d", "it", "go"], None),
+    // Ambiguous words only count where a quote was
+    (&["its", "memory", "usage"], None),
+    (&["we", "were", "well", "prepared"], None),
+    (&["lets", "and", "shell", "scripts"], None),
+    // Single letters that don't make a contraction
+    (&["plan", "B", "or", "plan", "d"], None),
+    (&["the", "letter", "t", "in", "words"], None),
+    (&["what", "is", "a", "t", "test"], None),
+    // Questions that were quoted properly
+    (&["what's the time?"], None),
+    (&["what is the time?"], None),
+    (&["how", "do", "I", "list", "files"], None),
+];
+
+#[test]
+fn mangled_questions_are_reconstructed() {
+    for (args, expected) in CASES {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        assert_eq!(detect(&args).as_deref(), *expected, "args: {:?}", args);
+    }
+}
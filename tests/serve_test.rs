@@ -0,0 +1,225 @@
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// A `qq serve` on a free port, stopped when dropped.
+struct Server {
+    child: Child,
+    // Kept open, since the server's prints fail once nobody reads them
+    _stdout: BufReader<ChildStdout>,
+    address: String,
+    _dir: TempDir,
+}
+
+impl Server {
+    fn start(extra_args: &[&str]) -> Self {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("config.toml"),
+            "provider = \"mock\"\nclaude_api_key = \"\"\nmodel = \"mock-model\"\nmax_tokens = 300\n",
+        )
+        .unwrap();
+
+        let mut child = Command::new(assert_cmd::cargo::cargo_bin("qq"))
+            .args(["serve", "--port", "0"])
+            .args(extra_args)
+            .env("QQ_CONFIG_DIR", dir.path())
+            .env_remove("QQ_PROVIDER")
+            .env_remove("QQ_MOCK_RESPONSE")
+            .env_remove("QQ_MOCK_SCRIPT")
+            .env_remove("QQ_SERVE_TOKEN")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let address = line.split("http://").nth(1).expect("address in the banner").trim().to_string();
+        Self {
+            child,
+            _stdout: stdout,
+            address,
+            _dir: dir,
+        }
+    }
+
+    /// Sends one request and returns the status and body.
+    fn send(&self, method: &str, path: &str, headers: &[&str], body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        let mut request = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, path, self.address);
+        for header in headers {
+            request.push_str(&format!("{}\r\n", header));
+        }
+        request.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+        stream.write_all(request.as_bytes()).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    fn post(&self, path: &str, body: Value) -> (u16, Value) {
+        let (status, body) = self.send("POST", path, &["Content-Type: application/json"], &body.to_string());
+        (status, serde_json::from_str(&body).unwrap())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn answers_questions_and_caches_them() {
+    let server = Server::start(&[]);
+
+    let (status, first) = server.post("/ask", json!({ "question": "What is Rust?" }));
+    assert_eq!(status, 200);
+    assert_eq!(first["answer"], "Mock answer to: What is Rust?");
+    assert_eq!(first["model"], "mock-model");
+    assert_eq!(first["cached"], false);
+
+    let (_, again) = server.post("/ask", json!({ "question": "What is Rust?" }));
+    assert_eq!(again["cached"], true);
+
+    let (_, fresh) = server.post("/ask", json!({ "question": "What is Rust?", "no_cache": true }));
+    assert_eq!(fresh["cached"], false);
+}
+
+#[test]
+fn chats_need_alternating_messages() {
+    let server = Server::start(&[]);
+
+    let (status, reply) = server.post(
+        "/chat",
+        json!({ "messages": [
+            { "role": "user", "content": "Hi" },
+            { "role": "assistant", "content": "Hello" },
+            { "role": "user", "content": "Bye" },
+        ] }),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(reply["answer"], "Mock answer to: Bye");
+
+    let (status, reply) = server.post(
+        "/chat",
+        json!({ "messages": [{ "role": "user", "content": "Hi" }, { "role": "assistant", "content": "Hello" }] }),
+    );
+    assert_eq!(status, 400);
+    assert!(reply["error"].as_str().unwrap().contains("end with one from the user"));
+}
+
+#[test]
+fn streams_answers_as_server_sent_events() {
+    let server = Server::start(&[]);
+
+    let (status, body) = server.send(
+        "POST",
+        "/ask",
+        &["Content-Type: application/json", "Accept: text/event-stream"],
+        &json!({ "question": "Stream it" }).to_string(),
+    );
+
+    assert_eq!(status, 200);
+    assert!(body.contains("event: delta\ndata: {\"text\":\"Mock answer to: Stream it\"}\n\n"));
+    let done = body.split("event: done\ndata: ").nth(1).expect("a done event");
+    let done: Value = serde_json::from_str(done.trim()).unwrap();
+    assert_eq!(done["answer"], "Mock answer to: Stream it");
+}
+
+#[test]
+fn history_lists_the_latest_answers() {
+    let server = Server::start(&[]);
+    for question in ["one", "two", "three"] {
+        server.post("/ask", json!({ "question": question }));
+    }
+
+    let (status, body) = server.send("GET", "/history?limit=2", &[], "");
+
+    assert_eq!(status, 200);
+    let history: Value = serde_json::from_str(&body).unwrap();
+    let questions: Vec<&str> = history["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["question"].as_str().unwrap())
+        .collect();
+    assert_eq!(questions, ["two", "three"]);
+}
+
+#[test]
+fn tokens_are_required_when_set() {
+    let server = Server::start(&["--token", "s3cret"]);
+    let ask = json!({ "question": "hi" }).to_string();
+
+    let (status, _) = server.send("POST", "/ask", &["Content-Type: application/json"], &ask);
+    assert_eq!(status, 401);
+
+    let (status, _) = server.send(
+        "POST",
+        "/ask",
+        &["Content-Type: application/json", "Authorization: Bearer wrong"],
+        &ask,
+    );
+    assert_eq!(status, 401);
+
+    let (status, _) = server.send(
+        "POST",
+        "/ask",
+        &["Content-Type: application/json", "Authorization: Bearer s3cret"],
+        &ask,
+    );
+    assert_eq!(status, 200);
+}
+
+#[test]
+fn browsers_are_turned_away_without_a_token() {
+    let server = Server::start(&[]);
+
+    let (status, _) = server.send("GET", "/health", &["Origin: https://example.com"], "");
+    assert_eq!(status, 403);
+
+    // A form post can't set a JSON content type, so it never gets asked
+    let (status, _) = server.send("POST", "/ask", &["Content-Type: text/plain"], "{\"question\": \"hi\"}");
+    assert_eq!(status, 415);
+
+    let (status, _) = server.send("GET", "/health", &[], "");
+    assert_eq!(status, 200);
+}
+
+#[test]
+fn idle_connections_time_out() {
+    let server = Server::start(&["--token", "s3cret"]);
+    let mut stream = TcpStream::connect(&server.address).unwrap();
+    stream.write_all(b"POST /ask HTTP/1.1\r\n").unwrap();
+
+    let started = Instant::now();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 408"));
+    assert!(started.elapsed() < Duration::from_secs(15));
+}
+
+#[test]
+fn models_are_written_as_in_compare() {
+    let server = Server::start(&[]);
+
+    let (status, reply) = server.post("/ask", json!({ "question": "hi", "model": "mock:other-model" }));
+    assert_eq!(status, 200);
+    assert_eq!(reply["model"], "other-model");
+
+    let (status, reply) = server.post("/ask", json!({ "question": "hi", "model": "nope:sonnet" }));
+    assert_eq!(status, 400);
+    assert!(reply["error"].as_str().unwrap().contains("Unknown provider 'nope'"));
+}
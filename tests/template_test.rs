@@ -0,0 +1,71 @@
+use quick_question::template::Template;
+use std::collections::HashMap;
+
+fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn parses_front_matter() {
+    let template = Template::parse(
+        "review",
+        "---\ndescription: \"Review a diff\"\nmodel: claude-haiku\nmax_tokens: 800\nsystem: |\n  You review code.\n  Be blunt.\n---\nReview this:\n\n{{input}}\n",
+    )
+    .unwrap();
+
+    assert_eq!(template.description.as_deref(), Some("Review a diff"));
+    assert_eq!(template.model.as_deref(), Some("claude-haiku"));
+    assert_eq!(template.max_tokens, Some(800));
+    assert_eq!(template.system.as_deref(), Some("You review code.\nBe blunt."));
+    assert_eq!(template.body, "Review this:\n\n{{input}}");
+}
+
+#[test]
+fn front_matter_is_optional() {
+    let template = Template::parse("plain", "Summarize: {{input}}").unwrap();
+
+    assert!(template.model.is_none());
+    assert_eq!(template.body, "Summarize: {{input}}");
+}
+
+#[test]
+fn rejects_bad_front_matter() {
+    assert!(Template::parse("t", "---\nmax_tokens: lots\n---\nhi").is_err());
+    assert!(Template::parse("t", "---\ntemperature: 2\n---\nhi").is_err());
+}
+
+#[test]
+fn fills_input_and_variables() {
+    let template = Template::parse("translate", "Translate into {{ lang }}:\n\n{{input}}").unwrap();
+
+    let filled = template.fill("good morning\n", &vars(&[("lang", "German")])).unwrap();
+
+    assert_eq!(filled, "Translate into German:\n\ngood morning");
+}
+
+#[test]
+fn missing_variables_are_named() {
+    let template = Template::parse("translate", "{{lang}} {{tone}} {{lang}} {{input}}").unwrap();
+
+    let error = template.fill("hi", &vars(&[])).unwrap_err().to_string();
+
+    assert!(error.contains("--var lang=... --var tone=..."), "{}", error);
+}
+
+#[test]
+fn input_is_appended_without_a_placeholder() {
+    let template = Template::parse("eli5", "Explain like I'm five.").unwrap();
+
+    assert!(!template.uses_input());
+    assert_eq!(template.fill("monads", &vars(&[])).unwrap(), "Explain like I'm five.\n\nmonads");
+    assert_eq!(template.fill("", &vars(&[])).unwrap(), "Explain like I'm five.");
+}
+
+#[test]
+fn names_cannot_leave_the_templates_directory() {
+    for name in ["../../.ssh/config", "a/b", "a\\b", "..", ".hidden", "a..b", ""] {
+        let error = Template::load(name).unwrap_err().to_string();
+        assert!(error.contains("isn't a valid template name"), "{}: {}", name, error);
+        assert!(Template::create(name).is_err());
+    }
+}
@@ -51,8 +51,8 @@ echo "- Strikethrough text with line through it"
 echo "- Unicode box characters preserved"
 echo
 
-# We would need to modify the code to allow testing the formatter directly
-# For now, this shows what the input looks like
+# The formatter itself is tested by `cargo test` (tests/format_test.rs)
+# This just shows what the input looks like
 echo "📝 Raw input (what Claude sends):"
 echo "**Bold text** *italic text* \`code\` ~~strikethrough~~"
 echo